@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+
+use backend::server::bot::Difficulty;
+use backend::server::map::Map;
+use backend::server::power_up::PowerUpWeights;
+use backend::server::types::{FieldHeightT, FieldWidthT, Wrap};
+use backend::server::world::{DeathCause, Seed, World, WorldSettings};
+use clap::Parser;
+use uuid::Uuid;
+
+/// Plays bot-only matches as fast as possible and prints per bot statistics.
+#[derive(Parser, Debug)]
+#[clap(name = "Multiplayer Snake Bot Tournament")]
+#[clap(version, about, long_about = None)]
+struct Args {
+    /// Number of matches to play
+    #[clap(long, value_parser, default_value_t = 10)]
+    matches: usize,
+
+    /// Ticks per match
+    #[clap(long, value_parser, default_value_t = 2000)]
+    ticks: u64,
+
+    /// Bots taking part, one per occurrence
+    #[clap(long = "bot", value_enum, default_values_t = vec![Difficulty::Easy, Difficulty::Normal, Difficulty::Hard])]
+    bots: Vec<Difficulty>,
+
+    /// Field width in blocks
+    #[clap(long, value_parser, default_value_t = 30)]
+    field_width: FieldWidthT,
+
+    /// Field height in blocks
+    #[clap(long, value_parser, default_value_t = 20)]
+    field_height: FieldHeightT,
+
+    /// Field edges snakes wrap around instead of dying
+    #[clap(long, value_enum, default_value_t = Wrap::None)]
+    wrap: Wrap,
+
+    /// Map file with walls, spawn points and food cells, sets the field size
+    #[clap(long, value_parser = Map::from_arg, conflicts_with_all = &["field-width", "field-height"])]
+    map: Option<Map>,
+
+    /// Food count on map
+    #[clap(long, value_parser, default_value_t = 10)]
+    food_count: usize,
+
+    /// Power-ups kept on the field
+    #[clap(long, value_parser, default_value_t = 0)]
+    power_ups: usize,
+
+    /// Seed of the first match, following matches use the next seeds
+    #[clap(long, value_parser)]
+    seed: Option<Seed>,
+}
+
+#[derive(Default)]
+struct Stats {
+    lives: u64,
+    total_score: u64,
+    ticks_alive: u64,
+    kills: u64,
+    deaths: u64,
+}
+
+impl Stats {
+    fn end_life(&mut self, score: usize, ticks: u64) {
+        self.lives += 1;
+        self.total_score += score as u64;
+        self.ticks_alive += ticks;
+    }
+
+    fn average(&self, total: u64) -> f64 {
+        if self.lives == 0 {
+            0.0
+        } else {
+            total as f64 / self.lives as f64
+        }
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    let first_seed = args.seed.unwrap_or_else(rand::random);
+    let map = args.map.clone().unwrap_or_else(|| Map {
+        field_width: args.field_width,
+        field_height: args.field_height,
+        ..Default::default()
+    });
+    let settings = WorldSettings {
+        field_width: map.field_width,
+        field_height: map.field_height,
+        food_count: args.food_count,
+        input_queue_len: 1,
+        sub_ticks: 2,
+        bot_move_interval: 0,
+        wrap: args.wrap,
+        walls: map.walls,
+        spawn_points: map.spawn_points,
+        food_zones: map.food_zones,
+        power_up_count: args.power_ups,
+        power_up_weights: PowerUpWeights::default(),
+        teams: 0,
+        friendly_fire: true,
+        respawn: true,
+    };
+
+    let bots: Vec<(Uuid, String, Difficulty)> = args
+        .bots
+        .iter()
+        .enumerate()
+        .map(|(index, difficulty)| {
+            let name = format!("Bot {} ({:?})", index + 1, difficulty);
+            (Uuid::from_u128(index as u128 + 1), name, *difficulty)
+        })
+        .collect();
+    if let Err(reason) = settings.validate(bots.len()) {
+        eprintln!("{}", reason);
+        std::process::exit(2);
+    }
+    let mut stats: HashMap<Uuid, Stats> = bots
+        .iter()
+        .map(|(uuid, _, _)| (*uuid, Stats::default()))
+        .collect();
+
+    for round in 0..args.matches {
+        let seed = first_seed.wrapping_add(round as Seed);
+        let mut world = World::new(settings.clone(), seed);
+        let mut born: HashMap<Uuid, u64> = HashMap::new();
+        for (uuid, name, difficulty) in &bots {
+            world.spawn_bot(*uuid, name.clone(), *difficulty);
+            born.insert(*uuid, 0);
+        }
+
+        for tick in 1..=args.ticks {
+            let events = world.step(&[]);
+            for death in events.deaths {
+                let bot_stats = stats.get_mut(&death.uuid).unwrap();
+                bot_stats.deaths += 1;
+                bot_stats.end_life(death.score, tick - born[&death.uuid]);
+                born.insert(death.uuid, tick);
+                if let DeathCause::Snake { killer } = death.cause {
+                    if let Some(killer_stats) = stats.get_mut(&killer) {
+                        killer_stats.kills += 1;
+                    }
+                }
+            }
+        }
+
+        for (uuid, player) in world.players() {
+            stats
+                .get_mut(uuid)
+                .unwrap()
+                .end_life(player.score, args.ticks - born[uuid]);
+        }
+    }
+
+    println!(
+        "{} matches of {} ticks, seeds {}..{}",
+        args.matches,
+        args.ticks,
+        first_seed,
+        first_seed.wrapping_add(args.matches as Seed)
+    );
+    println!(
+        "{:<20} {:>10} {:>14} {:>8} {:>8}",
+        "bot", "avg score", "avg survival", "kills", "deaths"
+    );
+    for (uuid, name, _) in &bots {
+        let bot_stats = &stats[uuid];
+        println!(
+            "{:<20} {:>10.2} {:>14.1} {:>8} {:>8}",
+            name,
+            bot_stats.average(bot_stats.total_score),
+            bot_stats.average(bot_stats.ticks_alive),
+            bot_stats.kills,
+            bot_stats.deaths
+        );
+    }
+}
@@ -1,3 +1,4 @@
 pub mod server;
 #[cfg(test)]
-mod tests;
\ No newline at end of file
+#[allow(clippy::module_inception)]
+mod tests;
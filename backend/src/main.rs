@@ -1,13 +1,18 @@
 use std::sync::Arc;
 
 use backend::server::{Args, Server};
-use clap::Parser;
 
 #[tokio::main]
 async fn main() {
     env_logger::init();
 
-    let args = Args::parse();
+    let args = match Args::load() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e:?}");
+            std::process::exit(2);
+        }
+    };
     let server = Arc::new(Server::new(args));
 
     server.run().await.unwrap();
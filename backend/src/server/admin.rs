@@ -0,0 +1,158 @@
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use uuid::Uuid;
+
+use super::types::{Name, RoomId, Score};
+
+/// Requests accepted on a connection opened with `ClientMessage::Admin`.
+#[derive(Serialize, Deserialize, Debug)]
+pub enum AdminCommand {
+    ListPlayers,
+    /// Removes the player from its room and closes its connection
+    Kick {
+        uuid: Uuid,
+    },
+    /// Kicks the player and refuses further connections from its address.
+    /// Fails for players without a live connection, their address is not
+    /// known.
+    Ban {
+        uuid: Uuid,
+    },
+    /// Refuses further connections from the address and kicks players
+    /// connected from it
+    BanAddress {
+        address: IpAddr,
+    },
+    /// Stops the game loop of a room, or of every room when missing
+    Pause {
+        #[serde(default)]
+        room: Option<RoomId>,
+    },
+    Resume {
+        #[serde(default)]
+        room: Option<RoomId>,
+    },
+    /// Changes settings of every room and of rooms created later
+    UpdateSettings(SettingsUpdate),
+    /// Reads the `--config` file again, like SIGHUP
+    ReloadConfig,
+    /// Outcome of the last reload, also one triggered by SIGHUP
+    LastReload,
+}
+
+/// Settings that can be changed without a restart, missing ones are kept.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SettingsUpdate {
+    #[serde(default)]
+    pub game_tick: Option<u64>,
+    #[serde(default)]
+    pub food_count: Option<usize>,
+    #[serde(default)]
+    pub max_players_count: Option<usize>,
+    #[serde(default)]
+    pub chat_max_len: Option<usize>,
+    #[serde(default)]
+    pub chat_interval: Option<u64>,
+    #[serde(default)]
+    pub chat_history: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum AdminResponse {
+    Players {
+        players: Vec<AdminPlayerInfo>,
+    },
+    Reloaded(ReloadReport),
+    /// `None` if the config was not reloaded yet
+    LastReload(Option<LastReload>),
+    Ok,
+    Error {
+        reason: String,
+    },
+}
+
+impl AdminResponse {
+    pub fn error(reason: impl Into<String>) -> Self {
+        AdminResponse::Error {
+            reason: reason.into(),
+        }
+    }
+}
+
+/// Compares an admin token with the expected one in a time that does not
+/// depend on where they differ.
+pub fn token_matches(expected: &str, given: &str) -> bool {
+    let expected = expected.as_bytes();
+    let mut diff = expected.len() ^ given.len();
+    for (i, byte) in given.bytes().enumerate() {
+        let other = expected.get(i).copied().unwrap_or_default();
+        diff |= (byte ^ other) as usize;
+    }
+    diff == 0
+}
+
+/// Outcome of reloading the config file.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReloadReport {
+    /// Changes applied to running rooms
+    pub applied: SettingsUpdate,
+    /// Changed options kept at their old value until the server restarts
+    pub needs_restart: Vec<String>,
+}
+
+/// Reload kept for `AdminCommand::LastReload`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LastReload {
+    /// Unix time in seconds
+    pub timestamp: u64,
+    /// The report, or why the config was not reloaded
+    pub result: Result<ReloadReport, String>,
+}
+
+/// Player as seen by an operator.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AdminPlayerInfo {
+    pub room: RoomId,
+    pub uuid: Uuid,
+    pub name: Name,
+    pub score: Score,
+    pub bot: bool,
+    /// False while waiting to be resumed
+    pub connected: bool,
+    pub address: Option<IpAddr>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_commands() {
+        let command: AdminCommand =
+            serde_json::from_str(r#"{"UpdateSettings": {"game_tick": 50}}"#).unwrap();
+        match command {
+            AdminCommand::UpdateSettings(update) => assert_eq!(
+                update,
+                SettingsUpdate {
+                    game_tick: Some(50),
+                    ..Default::default()
+                }
+            ),
+            _ => panic!("Expected UpdateSettings"),
+        }
+
+        let command: AdminCommand = serde_json::from_str(r#"{"Pause": {}}"#).unwrap();
+        assert!(matches!(command, AdminCommand::Pause { room: None }));
+        let command: AdminCommand = serde_json::from_str(r#""ListPlayers""#).unwrap();
+        assert!(matches!(command, AdminCommand::ListPlayers));
+    }
+
+    #[test]
+    fn compare_tokens() {
+        assert!(token_matches("secret", "secret"));
+        assert!(!token_matches("secret", "secreT"));
+        assert!(!token_matches("secret", "secret2"));
+        assert!(!token_matches("secret", "sec"));
+        assert!(!token_matches("secret", ""));
+    }
+}
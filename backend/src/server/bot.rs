@@ -0,0 +1,127 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use uuid::Uuid;
+
+use super::types::{Direction, Point};
+use super::world::World;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    /// Chance of ignoring the plan and taking a random safe turn.
+    fn mistake_rate(self) -> f64 {
+        match self {
+            Difficulty::Easy => 0.2,
+            Difficulty::Normal => 0.05,
+            Difficulty::Hard => 0.0,
+        }
+    }
+
+    fn checks_space(self) -> bool {
+        self != Difficulty::Easy
+    }
+}
+
+/// Picks the next direction for a bot: the first step of the shortest path
+/// to food, as long as it does not lead into an area too small for the
+/// snake. `roll` is a random number from `0.0..1.0` drawn by the world.
+pub fn choose_direction(
+    world: &World,
+    uuid: &Uuid,
+    difficulty: Difficulty,
+    roll: f64,
+) -> Option<Direction> {
+    let player = world.players().get(uuid)?;
+    let head = *player.snake.parts.front()?;
+    let current = player.snake.direction();
+    let length = player.snake.parts.len();
+
+    let safe: Vec<(Direction, usize)> = Direction::ALL
+        .into_iter()
+        .filter(|direction| *direction != current.opposite())
+        .map(|direction| (direction, world.neighbour(&head, direction)))
+        .filter(|(_, next)| world.is_free(next))
+        .map(|(direction, next)| {
+            let space = if difficulty.checks_space() {
+                flood_fill(world, next, length + 1)
+            } else {
+                length + 1
+            };
+            (direction, space)
+        })
+        .collect();
+    if safe.is_empty() {
+        return None;
+    }
+
+    let mistake_rate = difficulty.mistake_rate();
+    if roll < mistake_rate {
+        let index = (roll / mistake_rate * safe.len() as f64) as usize;
+        return Some(safe[index.min(safe.len() - 1)].0);
+    }
+
+    let roomy: Vec<Direction> = safe
+        .iter()
+        .filter(|(_, space)| *space > length)
+        .map(|(direction, _)| *direction)
+        .collect();
+    if let Some(direction) = path_to_food(world, head, current).filter(|d| roomy.contains(d)) {
+        return Some(direction);
+    }
+
+    safe.iter()
+        .max_by_key(|(direction, space)| (*space, *direction == current))
+        .map(|(direction, _)| *direction)
+}
+
+/// First step of the shortest path from `start` to any food.
+fn path_to_food(world: &World, start: Point, current: Direction) -> Option<Direction> {
+    let mut visited = HashSet::from([start]);
+    let mut queue = VecDeque::new();
+    for direction in Direction::ALL {
+        if direction == current.opposite() {
+            continue;
+        }
+        let next = world.neighbour(&start, direction);
+        if world.is_free(&next) && visited.insert(next) {
+            queue.push_back((next, direction));
+        }
+    }
+
+    while let Some((point, first_step)) = queue.pop_front() {
+        if world.has_food(&point) {
+            return Some(first_step);
+        }
+        for direction in Direction::ALL {
+            let next = world.neighbour(&point, direction);
+            if world.is_free(&next) && visited.insert(next) {
+                queue.push_back((next, first_step));
+            }
+        }
+    }
+    None
+}
+
+/// Counts free cells reachable from `start`, stopping at `limit`.
+fn flood_fill(world: &World, start: Point, limit: usize) -> usize {
+    let mut visited = HashSet::from([start]);
+    let mut queue = VecDeque::from([start]);
+    while let Some(point) = queue.pop_front() {
+        if visited.len() >= limit {
+            break;
+        }
+        for direction in Direction::ALL {
+            let next = world.neighbour(&point, direction);
+            if world.is_free(&next) && visited.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+    visited.len()
+}
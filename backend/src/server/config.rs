@@ -0,0 +1,349 @@
+use clap::ValueEnum;
+use error_stack::{IntoReport, Report, Result, ResultExt};
+use serde::Deserialize;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+use super::admin::SettingsUpdate;
+use super::errors::ConfigError;
+use super::map::Map;
+use super::power_up::PowerUpWeights;
+use super::room::RoomSettings;
+use super::types::{FieldHeightT, FieldWidthT, TeamId};
+use super::Args;
+
+/// Server configuration file, every key is optional and options given on
+/// the command line take precedence:
+///
+/// ```toml
+/// port = 43210
+/// http_port = 9100
+///
+/// [rooms]
+/// map = "arena.txt"
+/// game_tick = 80
+/// mode = "rounds"
+///
+/// [bots]
+/// count = 3
+/// difficulty = "hard"
+///
+/// [limits]
+/// max_players_count = 10
+/// chat_interval = 500
+/// ```
+///
+/// Enum values and `power_up_weights` are written as on the command line.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub address: Option<IpAddr>,
+    pub port: Option<u16>,
+    pub http_port: Option<u16>,
+    pub record_dir: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    pub leaderboard: Option<PathBuf>,
+    pub leaderboard_size: Option<usize>,
+    pub leaderboard_interval: Option<u64>,
+    pub admin_token: Option<String>,
+    pub banned_names: Option<PathBuf>,
+    pub rooms: RoomsConfig,
+    pub bots: BotsConfig,
+    pub limits: LimitsConfig,
+}
+
+/// Game played in every room.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoomsConfig {
+    pub field_width: Option<FieldWidthT>,
+    pub field_height: Option<FieldHeightT>,
+    pub wrap: Option<String>,
+    /// Map file, relative to the configuration file
+    pub map: Option<PathBuf>,
+    pub game_tick: Option<u64>,
+    pub food_count: Option<usize>,
+    pub teams: Option<TeamId>,
+    pub friendly_fire: Option<bool>,
+    pub mode: Option<String>,
+    pub lobby_countdown: Option<u64>,
+    pub power_ups: Option<usize>,
+    pub power_up_weights: Option<String>,
+    pub sub_ticks: Option<u32>,
+    pub keyframe_interval: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct BotsConfig {
+    pub count: Option<usize>,
+    pub difficulty: Option<String>,
+    pub move_interval: Option<u32>,
+}
+
+/// Limits on what players can do.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    pub max_players_count: Option<usize>,
+    pub input_queue_len: Option<usize>,
+    pub resume_grace: Option<u64>,
+    pub chat_max_len: Option<usize>,
+    pub chat_interval: Option<u64>,
+    pub chat_history: Option<usize>,
+    pub rate_limit_messages: Option<u32>,
+    pub rate_limit_bytes: Option<u32>,
+    pub rate_limit_warnings: Option<u32>,
+    pub name_min_len: Option<usize>,
+    pub name_max_len: Option<usize>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path)
+            .report()
+            .change_context(ConfigError)
+            .attach_printable_lazy(|| format!("Unable to read {}", path.display()))?;
+        let mut config: Config = toml::from_str(&text)
+            .report()
+            .change_context(ConfigError)
+            .attach_printable_lazy(|| format!("Invalid config {}", path.display()))?;
+        if let (Some(map), Some(dir)) = (&config.rooms.map, path.parent()) {
+            config.rooms.map = Some(dir.join(map));
+        }
+        Ok(config)
+    }
+
+    /// Sets every option of `args` found in the file, unless `from_cli`
+    /// tells it was given on the command line. `from_cli` gets clap's id
+    /// of the option.
+    pub fn apply(
+        &self,
+        args: &mut Args,
+        from_cli: impl Fn(&str) -> bool,
+    ) -> Result<(), ConfigError> {
+        macro_rules! layer {
+            ($id:literal, $field:ident, $value:expr) => {
+                if let Some(value) = $value {
+                    if !from_cli($id) {
+                        args.$field = value;
+                    }
+                }
+            };
+        }
+
+        layer!("address", address, self.address);
+        layer!("port", port, self.port);
+        layer!("http-port", http_port, self.http_port.map(Some));
+        layer!("record-dir", record_dir, self.record_dir.clone().map(Some));
+        layer!("replay", replay, self.replay.clone().map(Some));
+        layer!(
+            "leaderboard",
+            leaderboard,
+            self.leaderboard.clone().map(Some)
+        );
+        layer!("leaderboard-size", leaderboard_size, self.leaderboard_size);
+        layer!(
+            "leaderboard-interval",
+            leaderboard_interval,
+            self.leaderboard_interval
+        );
+        layer!(
+            "admin-token",
+            admin_token,
+            self.admin_token.clone().map(Some)
+        );
+
+        layer!(
+            "banned-names",
+            banned_names,
+            self.banned_names.clone().map(Some)
+        );
+
+        let rooms = &self.rooms;
+        layer!("field-width", field_width, rooms.field_width);
+        layer!("field-height", field_height, rooms.field_height);
+        layer!("wrap", wrap, parse_enum("rooms.wrap", &rooms.wrap)?);
+        if let Some(path) = &rooms.map {
+            // The command line field size replaces the file's map too.
+            if !from_cli("map") && !from_cli("field-width") && !from_cli("field-height") {
+                args.map = Some(
+                    Map::load(path)
+                        .change_context(ConfigError)
+                        .attach_printable("Invalid rooms.map")?,
+                );
+            }
+        }
+        layer!("game-tick", game_tick, rooms.game_tick);
+        layer!("food-count", food_count, rooms.food_count);
+        layer!("teams", teams, rooms.teams);
+        layer!("friendly-fire", friendly_fire, rooms.friendly_fire);
+        layer!("mode", mode, parse_enum("rooms.mode", &rooms.mode)?);
+        layer!("lobby-countdown", lobby_countdown, rooms.lobby_countdown);
+        layer!("power-ups", power_ups, rooms.power_ups);
+        let weights = match &rooms.power_up_weights {
+            Some(weights) => Some(PowerUpWeights::from_arg(weights).map_err(|e| {
+                Report::new(ConfigError)
+                    .attach_printable(format!("Invalid rooms.power_up_weights: {}", e))
+            })?),
+            None => None,
+        };
+        layer!("power-up-weights", power_up_weights, weights);
+        layer!("sub-ticks", sub_ticks, rooms.sub_ticks);
+        layer!(
+            "keyframe-interval",
+            keyframe_interval,
+            rooms.keyframe_interval
+        );
+
+        layer!("bots", bots, self.bots.count);
+        layer!(
+            "bot-difficulty",
+            bot_difficulty,
+            parse_enum("bots.difficulty", &self.bots.difficulty)?
+        );
+        layer!(
+            "bot-move-interval",
+            bot_move_interval,
+            self.bots.move_interval
+        );
+
+        let limits = &self.limits;
+        layer!(
+            "max-players-count",
+            max_players_count,
+            limits.max_players_count
+        );
+        layer!("input-queue-len", input_queue_len, limits.input_queue_len);
+        layer!("resume-grace", resume_grace, limits.resume_grace);
+        layer!("chat-max-len", chat_max_len, limits.chat_max_len);
+        layer!("chat-interval", chat_interval, limits.chat_interval);
+        layer!("chat-history", chat_history, limits.chat_history);
+        layer!(
+            "rate-limit-messages",
+            rate_limit_messages,
+            limits.rate_limit_messages
+        );
+        layer!(
+            "rate-limit-bytes",
+            rate_limit_bytes,
+            limits.rate_limit_bytes
+        );
+        layer!(
+            "rate-limit-warnings",
+            rate_limit_warnings,
+            limits.rate_limit_warnings
+        );
+        layer!("name-min-len", name_min_len, limits.name_min_len);
+        layer!("name-max-len", name_max_len, limits.name_max_len);
+
+        Ok(())
+    }
+}
+
+/// Splits the differences between running and reloaded settings into
+/// changes that can be applied live and names of those needing a restart.
+pub fn settings_changes(
+    current: &RoomSettings,
+    reloaded: &RoomSettings,
+) -> (SettingsUpdate, Vec<String>) {
+    macro_rules! changed {
+        ($field:ident) => {
+            (current.$field != reloaded.$field).then_some(reloaded.$field)
+        };
+    }
+    let update = SettingsUpdate {
+        game_tick: changed!(game_tick),
+        food_count: changed!(food_count),
+        max_players_count: changed!(max_players_count),
+        chat_max_len: changed!(chat_max_len),
+        chat_interval: changed!(chat_interval),
+        chat_history: changed!(chat_history),
+    };
+
+    let mut updated = current.clone();
+    updated.apply(&update);
+    let needs_restart = match (
+        serde_json::to_value(&updated),
+        serde_json::to_value(reloaded),
+    ) {
+        (Ok(serde_json::Value::Object(updated)), Ok(serde_json::Value::Object(reloaded))) => {
+            reloaded
+                .into_iter()
+                .filter(|(key, value)| updated.get(key) != Some(value))
+                .map(|(key, _)| key)
+                .collect()
+        }
+        _ => Vec::new(),
+    };
+
+    (update, needs_restart)
+}
+
+/// Parses an enum value spelled as on the command line.
+fn parse_enum<T: ValueEnum>(key: &str, value: &Option<String>) -> Result<Option<T>, ConfigError> {
+    value
+        .as_deref()
+        .map(|value| {
+            T::from_str(value, true).map_err(|e| {
+                Report::new(ConfigError).attach_printable(format!("Invalid {}: {}", key, e))
+            })
+        })
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::room::GameMode;
+    use clap::Parser;
+
+    #[test]
+    fn command_line_overrides_file() {
+        let config: Config = toml::from_str(
+            r#"
+            port = 1234
+            [rooms]
+            game_tick = 50
+            mode = "rounds"
+            [limits]
+            chat_interval = 10
+            "#,
+        )
+        .unwrap();
+        let mut args = Args::parse_from(["backend", "-t", "70"]);
+
+        config.apply(&mut args, |id| id == "game-tick").unwrap();
+
+        assert_eq!(args.port, 1234);
+        assert_eq!(args.game_tick, 70);
+        assert_eq!(args.mode, GameMode::Rounds);
+        assert_eq!(args.chat_interval, 10);
+    }
+
+    #[test]
+    fn split_live_and_restart_changes() {
+        let current = RoomSettings::from(&Args::parse_from(["backend"]));
+        let reloaded = RoomSettings::from(&Args::parse_from(["backend", "-t", "50", "-w", "40"]));
+
+        let (update, needs_restart) = settings_changes(&current, &reloaded);
+
+        assert_eq!(
+            update,
+            SettingsUpdate {
+                game_tick: Some(50),
+                ..Default::default()
+            }
+        );
+        assert_eq!(needs_restart, vec!["field_width".to_string()]);
+    }
+
+    #[test]
+    fn reject_invalid_values() {
+        assert!(toml::from_str::<Config>("[rooms]\nspeed = 3").is_err());
+
+        let config: Config = toml::from_str("[rooms]\nwrap = \"diagonal\"").unwrap();
+        let mut args = Args::parse_from(["backend"]);
+        assert!(config.apply(&mut args, |_| false).is_err());
+    }
+}
@@ -13,6 +13,21 @@ pub struct GameError;
 #[derive(Debug)]
 pub struct SendError;
 
+#[derive(Debug)]
+pub struct ReplayError;
+
+#[derive(Debug)]
+pub struct MapError;
+
+#[derive(Debug)]
+pub struct LeaderboardError;
+
+#[derive(Debug)]
+pub struct HttpError;
+
+#[derive(Debug)]
+pub struct ConfigError;
+
 impl fmt::Display for ServerError {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
         fmt.write_str("Server error")
@@ -34,8 +49,38 @@ impl fmt::Display for SendError {
         fmt.write_str("Send message error")
     }
 }
+impl fmt::Display for ReplayError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str("Replay error")
+    }
+}
+impl fmt::Display for MapError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str("Map error")
+    }
+}
+impl fmt::Display for LeaderboardError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str("Leaderboard error")
+    }
+}
+impl fmt::Display for HttpError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str("HTTP error")
+    }
+}
+impl fmt::Display for ConfigError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_str("Configuration error")
+    }
+}
 
 impl Context for ServerError {}
 impl Context for ConnectionError {}
 impl Context for GameError {}
 impl Context for SendError {}
+impl Context for ReplayError {}
+impl Context for MapError {}
+impl Context for LeaderboardError {}
+impl Context for HttpError {}
+impl Context for ConfigError {}
@@ -0,0 +1,181 @@
+use error_stack::{IntoReport, Result, ResultExt};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use uuid::Uuid;
+
+use super::errors::HttpError;
+use super::types::{FieldHeightT, FieldWidthT, Name, RoomId, Score, TeamId};
+
+/// Requests with longer headers are refused.
+const MAX_REQUEST_LEN: usize = 8 * 1024;
+
+/// Body of `GET /status`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ServerStatus {
+    pub version: String,
+    /// Seconds since the server started
+    pub uptime: u64,
+    pub field_width: FieldWidthT,
+    pub field_height: FieldHeightT,
+    /// Game tick in miliseconds
+    pub game_tick: u64,
+    /// Human players in all rooms
+    pub player_count: usize,
+    pub max_players_count: usize,
+}
+
+/// Entry of `GET /players`, a `PlayerInfo` without the snake's body.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PlayerSummary {
+    pub room: RoomId,
+    pub uuid: Uuid,
+    pub name: Name,
+    pub score: Score,
+    pub bot: bool,
+    pub team: Option<TeamId>,
+    /// False while waiting for the next round
+    pub alive: bool,
+}
+
+/// Response of the plain HTTP endpoint.
+pub struct HttpResponse {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl HttpResponse {
+    pub fn ok(content_type: &'static str, body: String) -> Self {
+        HttpResponse {
+            status: 200,
+            content_type,
+            body,
+        }
+    }
+
+    pub fn json(body: &impl Serialize) -> Self {
+        match serde_json::to_string(body) {
+            Ok(body) => HttpResponse::ok("application/json", body),
+            Err(_) => HttpResponse::error(500),
+        }
+    }
+
+    pub fn error(status: u16) -> Self {
+        HttpResponse {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: format!("{}\n", reason_phrase(status)),
+        }
+    }
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Answers a single HTTP/1.1 request and closes the connection. Only `GET`
+/// is supported, `route` gets the path without the query string. Any origin
+/// may read responses, so browser pages can query the server.
+pub async fn serve_request(
+    mut stream: TcpStream,
+    route: impl FnOnce(&str) -> HttpResponse,
+) -> Result<(), HttpError> {
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream
+            .read(&mut buffer)
+            .await
+            .report()
+            .change_context(HttpError)
+            .attach_printable("Unable to read request")?;
+        if read == 0 {
+            return Ok(());
+        }
+        request.extend_from_slice(&buffer[..read]);
+        if request.len() > MAX_REQUEST_LEN {
+            break;
+        }
+    }
+
+    let request_line = String::from_utf8_lossy(&request)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let mut parts = request_line.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        _ if request.len() > MAX_REQUEST_LEN => HttpResponse::error(400),
+        (Some("GET"), Some(target)) => route(target.split('?').next().unwrap_or_default()),
+        (Some(_), Some(_)) => HttpResponse::error(405),
+        _ => HttpResponse::error(400),
+    };
+
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason_phrase(response.status),
+        response.content_type,
+        response.body.len()
+    );
+    stream
+        .write_all(head.as_bytes())
+        .await
+        .report()
+        .change_context(HttpError)?;
+    stream
+        .write_all(response.body.as_bytes())
+        .await
+        .report()
+        .change_context(HttpError)
+        .attach_printable("Unable to write response")?;
+    _ = stream.shutdown().await;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    async fn request(raw: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            serve_request(stream, |path| match path {
+                "/status" => HttpResponse::ok("text/plain", "up".to_string()),
+                _ => HttpResponse::error(404),
+            })
+            .await
+            .unwrap();
+        });
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(raw.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        server.await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn routes_get_requests() {
+        let response = request("GET /status?pretty HTTP/1.1\r\nHost: x\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Length: 2\r\n"));
+        assert!(response.ends_with("\r\n\r\nup"));
+
+        let response = request("GET /nope HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        let response = request("POST /status HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+    }
+}
@@ -0,0 +1,268 @@
+use error_stack::{IntoReport, Result, ResultExt};
+use log::warn;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::JoinHandle;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::errors::LeaderboardError;
+use super::types::{Name, Score};
+
+/// Best results of a player, identified by name.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LeaderboardEntry {
+    pub name: Name,
+    pub score: Score,
+    pub length: usize,
+    /// Unix time in seconds of the last improvement
+    pub timestamp: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<Name, LeaderboardEntry>,
+    /// Improvements waiting to be appended to the file
+    writer: Option<Sender<LeaderboardEntry>>,
+}
+
+/// Best score and length of every player, shared by all rooms. Once opened
+/// with a file every improvement is appended to it as a JSON line, and
+/// loading keeps the best line per name.
+///
+/// Players are told apart by name only, so players with the same name share
+/// an entry even when they play in different rooms.
+#[derive(Default)]
+pub struct Leaderboard {
+    inner: Mutex<Inner>,
+    writer_thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Leaderboard {
+    /// Loads entries from the file, creating it if missing, and appends
+    /// further improvements to it from a separate thread. Invalid lines, like
+    /// one cut short by a crash, are skipped.
+    pub fn open(&self, path: &Path) -> Result<(), LeaderboardError> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)
+            .report()
+            .change_context(LeaderboardError)
+            .attach_printable_lazy(|| format!("Unable to open {}", path.display()))?;
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)
+            .report()
+            .change_context(LeaderboardError)
+            .attach_printable_lazy(|| format!("Unable to read {}", path.display()))?;
+
+        let mut inner = self.inner.lock();
+        for (line_number, line) in String::from_utf8_lossy(&content).lines().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            match serde_json::from_str(line) {
+                Ok(entry) => {
+                    Inner::merge(&mut inner.entries, entry);
+                }
+                Err(e) => warn!(
+                    "Skipping invalid leaderboard entry in line {}: {}",
+                    line_number + 1,
+                    e
+                ),
+            }
+        }
+        if content.last().is_some_and(|byte| *byte != b'\n') {
+            // Not appending to a line cut short.
+            file.write_all(b"\n")
+                .report()
+                .change_context(LeaderboardError)
+                .attach_printable("Unable to write leaderboard file")?;
+        }
+
+        let (tx, rx) = channel();
+        let handle = std::thread::Builder::new()
+            .name("leaderboard".to_string())
+            .spawn(move || write_entries(file, rx))
+            .report()
+            .change_context(LeaderboardError)?;
+        inner.writer = Some(tx);
+        *self.writer_thread.lock() = Some(handle);
+
+        Ok(())
+    }
+
+    /// Records a finished life, returns whether it improved the player's
+    /// best score or length. Does not wait for the file to be written.
+    pub fn submit(&self, name: &str, score: Score, length: usize) -> bool {
+        if score == 0 {
+            return false;
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let entry = LeaderboardEntry {
+            name: name.to_string(),
+            score,
+            length,
+            timestamp,
+        };
+
+        let mut inner = self.inner.lock();
+        let improved = Inner::merge(&mut inner.entries, entry);
+        if let (Some(improved), Some(writer)) = (&improved, &inner.writer) {
+            _ = writer.send(improved.clone());
+        }
+
+        improved.is_some()
+    }
+
+    /// Best `count` players, highest score first.
+    pub fn top(&self, count: usize) -> Vec<LeaderboardEntry> {
+        let mut entries: Vec<LeaderboardEntry> =
+            self.inner.lock().entries.values().cloned().collect();
+        entries.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then(b.length.cmp(&a.length))
+                .then(a.timestamp.cmp(&b.timestamp))
+                .then(a.name.cmp(&b.name))
+        });
+        entries.truncate(count);
+        entries
+    }
+}
+
+impl Drop for Leaderboard {
+    /// Waits for pending entries to be written.
+    fn drop(&mut self) {
+        self.inner.get_mut().writer = None;
+        if let Some(handle) = self.writer_thread.get_mut().take() {
+            _ = handle.join();
+        }
+    }
+}
+
+/// Appends entries to the file as JSON lines until the leaderboard is
+/// dropped, so a slow disk does not hold up the game loops.
+fn write_entries(mut file: File, entries: Receiver<LeaderboardEntry>) {
+    for entry in entries {
+        let result = serde_json::to_vec(&entry)
+            .map_err(|e| e.to_string())
+            .and_then(|mut line| {
+                line.push(b'\n');
+                file.write_all(&line).map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            warn!("Unable to write leaderboard file: {}", e);
+        }
+    }
+}
+
+impl Inner {
+    /// Keeps the better score and length, returns the updated entry if
+    /// anything improved.
+    fn merge(
+        entries: &mut HashMap<Name, LeaderboardEntry>,
+        entry: LeaderboardEntry,
+    ) -> Option<LeaderboardEntry> {
+        match entries.get_mut(&entry.name) {
+            Some(best) if entry.score <= best.score && entry.length <= best.length => None,
+            Some(best) => {
+                best.score = best.score.max(entry.score);
+                best.length = best.length.max(entry.length);
+                best.timestamp = entry.timestamp;
+                Some(best.clone())
+            }
+            None => {
+                entries.insert(entry.name.clone(), entry.clone());
+                Some(entry)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_best_score_and_length() {
+        let leaderboard = Leaderboard::default();
+        assert!(leaderboard.submit("a", 5, 3));
+        assert!(!leaderboard.submit("a", 4, 2));
+        assert!(leaderboard.submit("a", 2, 8));
+        assert!(leaderboard.submit("b", 9, 4));
+        assert!(!leaderboard.submit("c", 0, 1));
+
+        let top = leaderboard.top(10);
+        let results: Vec<(&str, Score, usize)> = top
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.score, entry.length))
+            .collect();
+        assert_eq!(results, vec![("b", 9, 4), ("a", 5, 8)]);
+        assert_eq!(leaderboard.top(1).len(), 1);
+    }
+
+    #[test]
+    fn survives_restart() {
+        let path = std::env::temp_dir().join(format!("leaderboard-{}.jsonl", uuid::Uuid::new_v4()));
+        let leaderboard = Leaderboard::default();
+        leaderboard.open(&path).unwrap();
+        leaderboard.submit("a", 5, 3);
+        leaderboard.submit("a", 7, 4);
+        drop(leaderboard);
+
+        let leaderboard = Leaderboard::default();
+        leaderboard.open(&path).unwrap();
+        let top = leaderboard.top(10);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(top.len(), 1);
+        assert_eq!((top[0].score, top[0].length), (7, 4));
+    }
+
+    #[test]
+    fn skips_line_cut_short() {
+        let path = std::env::temp_dir().join(format!("leaderboard-{}.jsonl", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            "{\"name\":\"a\",\"score\":3,\"length\":2,\"timestamp\":1}\n{\"name\":\"b\",\"sc",
+        )
+        .unwrap();
+        let leaderboard = Leaderboard::default();
+        leaderboard.open(&path).unwrap();
+        leaderboard.submit("c", 5, 1);
+        drop(leaderboard);
+
+        let leaderboard = Leaderboard::default();
+        leaderboard.open(&path).unwrap();
+        let names: Vec<Name> = leaderboard
+            .top(10)
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(names, vec!["c".to_string(), "a".to_string()]);
+    }
+
+    #[test]
+    fn names_are_shared_across_rooms() {
+        // Two players called "bob" in different rooms submit to the same
+        // leaderboard.
+        let leaderboard = Leaderboard::default();
+        assert!(leaderboard.submit("bob", 4, 5));
+        assert!(!leaderboard.submit("bob", 3, 2));
+
+        let top = leaderboard.top(10);
+        assert_eq!(top.len(), 1);
+        assert_eq!((top[0].score, top[0].length), (4, 5));
+    }
+}
@@ -0,0 +1,128 @@
+use error_stack::{IntoReport, Report, Result, ResultExt};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use super::errors::MapError;
+use super::types::{FieldHeightT, FieldWidthT, Point};
+
+const WALL: char = '#';
+const EMPTY: char = '.';
+const SPAWN_POINT: char = 'S';
+const FOOD_ZONE: char = 'F';
+
+/// Field layout loaded from a text grid, one character per cell:
+///
+/// ```text
+/// ##########
+/// #S..FF..S#
+/// #...##...#
+/// ##########
+/// ```
+///
+/// `#` is a wall, `.` an empty cell, `S` a spawn point and `F` a cell food
+/// can appear on. Without spawn points snakes spawn on any free cell, without
+/// food cells food appears anywhere. Lines starting with `;` are comments.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Map {
+    pub field_width: FieldWidthT,
+    pub field_height: FieldHeightT,
+    pub walls: Vec<Point>,
+    pub spawn_points: Vec<Point>,
+    pub food_zones: Vec<Point>,
+}
+
+impl Map {
+    pub fn load(path: &Path) -> Result<Self, MapError> {
+        let text = std::fs::read_to_string(path)
+            .report()
+            .change_context(MapError)
+            .attach_printable_lazy(|| format!("Unable to read {}", path.display()))?;
+        Map::parse(&text).attach_printable_lazy(|| format!("Invalid map {}", path.display()))
+    }
+
+    /// Value parser for the `--map` command line option.
+    pub fn from_arg(path: &str) -> std::result::Result<Self, String> {
+        Map::load(Path::new(path)).map_err(|e| format!("{:?}", e))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, MapError> {
+        let rows: Vec<&str> = text
+            .lines()
+            .map(str::trim_end)
+            .filter(|line| !line.is_empty() && !line.starts_with(';'))
+            .collect();
+
+        let field_width = rows.first().map(|row| row.chars().count()).unwrap_or(0);
+        if field_width == 0 {
+            return Err(Report::new(MapError).attach_printable("Map is empty"));
+        }
+
+        let mut map = Map {
+            field_width: field_width as FieldWidthT,
+            field_height: rows.len() as FieldHeightT,
+            ..Default::default()
+        };
+        for (y, row) in rows.iter().enumerate() {
+            if row.chars().count() != field_width {
+                return Err(Report::new(MapError).attach_printable(format!(
+                    "Row {} is not {} cells wide",
+                    y + 1,
+                    field_width
+                )));
+            }
+            for (x, cell) in row.chars().enumerate() {
+                let point = Point {
+                    x: x as FieldWidthT,
+                    y: y as FieldHeightT,
+                };
+                match cell {
+                    WALL => map.walls.push(point),
+                    SPAWN_POINT => map.spawn_points.push(point),
+                    FOOD_ZONE => map.food_zones.push(point),
+                    EMPTY => (),
+                    _ => {
+                        return Err(Report::new(MapError).attach_printable(format!(
+                            "Unknown cell '{}' in row {}",
+                            cell,
+                            y + 1
+                        )))
+                    }
+                }
+            }
+        }
+
+        if map.walls.len() == field_width * rows.len() {
+            return Err(Report::new(MapError).attach_printable("Map has no free cells"));
+        }
+
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_grid() {
+        let map = Map::parse(
+            "; arena\n\
+             #####\n\
+             #S.F#\n\
+             #####\n",
+        )
+        .unwrap();
+
+        assert_eq!((map.field_width, map.field_height), (5, 3));
+        assert_eq!(map.walls.len(), 12);
+        assert_eq!(map.spawn_points, vec![Point { x: 1, y: 1 }]);
+        assert_eq!(map.food_zones, vec![Point { x: 3, y: 1 }]);
+    }
+
+    #[test]
+    fn reject_ragged_rows() {
+        assert!(Map::parse("###\n##\n").is_err());
+        assert!(Map::parse("#x#\n").is_err());
+        assert!(Map::parse("").is_err());
+    }
+}
@@ -3,14 +3,99 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use super::{
-    types::{FieldHeightT, FieldWidthT, PlayerInfo},
+    leaderboard::LeaderboardEntry,
+    power_up::PowerUp,
+    types::{
+        FieldHeightT, FieldWidthT, Name, PlayerInfo, ResumeToken, RoomId, Score, SnakeMove,
+        Standing, TeamId, Wrap,
+    },
+    world::{Tick, TickEvents, World},
     Point,
 };
 
+pub const DEFAULT_ROOM: &str = "default";
+
+pub const JSON_SUBPROTOCOL: &str = "snake.json";
+pub const MESSAGE_PACK_SUBPROTOCOL: &str = "snake.msgpack";
+
+/// Wire format of server messages. JSON is sent as text frames, MessagePack
+/// as binary frames.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    #[default]
+    Json,
+    MessagePack,
+}
+
+impl Encoding {
+    pub fn subprotocol(self) -> &'static str {
+        match self {
+            Encoding::Json => JSON_SUBPROTOCOL,
+            Encoding::MessagePack => MESSAGE_PACK_SUBPROTOCOL,
+        }
+    }
+
+    pub fn from_subprotocol(name: &str) -> Option<Self> {
+        match name.trim() {
+            JSON_SUBPROTOCOL => Some(Encoding::Json),
+            MESSAGE_PACK_SUBPROTOCOL => Some(Encoding::MessagePack),
+            _ => None,
+        }
+    }
+}
+
+pub fn default_room() -> RoomId {
+    DEFAULT_ROOM.into()
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub enum ClientMessage {
-    Register { name: String },
-    Turn { direction: Direction },
+    Register {
+        name: String,
+        #[serde(default = "default_room")]
+        room: RoomId,
+        /// Receive `ServerMessage::Delta` between keyframes
+        #[serde(default)]
+        delta: bool,
+        /// Overrides the encoding negotiated with websocket subprotocol
+        #[serde(default)]
+        encoding: Option<Encoding>,
+        /// Team to join in team mode, the smallest team when missing
+        #[serde(default)]
+        team: Option<TeamId>,
+    },
+    Spectate {
+        #[serde(default = "default_room")]
+        room: RoomId,
+        #[serde(default)]
+        delta: bool,
+        #[serde(default)]
+        encoding: Option<Encoding>,
+    },
+    /// Reattach to a player kept after a dropped connection
+    Resume {
+        token: ResumeToken,
+        #[serde(default)]
+        delta: bool,
+        #[serde(default)]
+        encoding: Option<Encoding>,
+    },
+    /// Open an operator connection taking `AdminCommand`s, the token must
+    /// match `--admin-token`
+    Admin {
+        token: String,
+    },
+    Turn {
+        direction: Direction,
+    },
+    /// Ask for a full `ServerMessage::Turn` on the next tick
+    Resync,
+    /// Ask for a `ServerMessage::Leaderboard`
+    GetLeaderboard,
+    /// Message to everyone in the room
+    Chat {
+        text: String,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -18,12 +103,97 @@ pub enum ServerMessage {
     Register {
         field_width: FieldWidthT,
         field_height: FieldHeightT,
+        /// Edges snakes pass through
+        wrap: Wrap,
+        /// Cells taken by the map's walls
+        walls: Vec<Point>,
         uuid: Uuid,
+        /// Send in `ClientMessage::Resume` to get the snake back after a
+        /// dropped connection
+        resume_token: Option<ResumeToken>,
+        team: Option<TeamId>,
+        /// Player's name after cleaning and de-duplication
+        #[serde(default)]
+        name: Option<Name>,
     },
+    /// Full game state, a keyframe for delta clients
     Turn {
+        tick: Tick,
         players: Vec<PlayerInfo>,
         food: Vec<Point>,
+        power_ups: Vec<(Point, PowerUp)>,
+        /// Summed scores of team members, indexed by team id
+        team_scores: Vec<Score>,
+    },
+    /// Changes since the previous tick. Applied in order: moves, eaten food,
+    /// picked up power-ups, tail parts cut off by shrinking, deaths, respawns,
+    /// spawned food and spawned power-ups.
+    Delta {
+        tick: Tick,
+        moves: Vec<SnakeMove>,
+        /// Eaten food with the snake's new score, which also counts
+        /// `PowerUp::ScoreMultiplier`
+        eaten: Vec<(Uuid, Point, Score)>,
+        deaths: Vec<Uuid>,
+        respawns: Vec<(Uuid, Point)>,
+        spawned_food: Vec<Point>,
+        picked_up: Vec<(Uuid, Point)>,
+        shrunk: Vec<(Uuid, usize)>,
+        spawned_power_ups: Vec<(Point, PowerUp)>,
     },
+    /// Sent every tick between rounds, `starts_in` milliseconds once enough
+    /// players joined
+    Lobby { starts_in: Option<u64> },
+    /// Round ended, `winner` is the last snake standing if there was one.
+    /// Standings are ordered best first.
+    RoundOver {
+        winner: Option<Uuid>,
+        standings: Vec<Standing>,
+    },
+    /// Best players of all time, highest score first. Also pushed
+    /// periodically.
+    Leaderboard { entries: Vec<LeaderboardEntry> },
+    /// Chat message of player `from`, `ts` is the unix time in milliseconds.
+    /// Recent messages are sent after `Register`.
+    Chat {
+        from: Uuid,
+        name: Name,
+        text: String,
+        ts: u64,
+    },
+    /// Something the client should stop doing, the connection stays open
+    Warning { reason: String },
+    /// Request was rejected, connection is closed afterwards
+    Error { reason: String },
+}
+
+impl ServerMessage {
+    pub fn keyframe(world: &World) -> Self {
+        ServerMessage::Turn {
+            tick: world.tick(),
+            players: world.player_infos(),
+            food: world.food().copied().collect(),
+            power_ups: world
+                .power_ups()
+                .map(|(point, power_up)| (*point, *power_up))
+                .collect(),
+            team_scores: world.team_scores(),
+        }
+    }
+
+    pub fn delta(events: &TickEvents) -> Self {
+        ServerMessage::Delta {
+            tick: events.tick,
+            moves: events.moves.clone(),
+            eaten: events.eaten.clone(),
+            deaths: events.deaths.iter().map(|death| death.uuid).collect(),
+            respawns: events.respawned.clone(),
+            spawned_food: events.spawned_food.clone(),
+            picked_up: events.picked_up.clone(),
+            shrunk: events.shrunk.clone(),
+            spawned_power_ups: events.spawned_power_ups.clone(),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -38,6 +208,7 @@ mod tests {
     #[test]
     fn serialization() {
         let msg = ServerMessage::Turn {
+            tick: 1,
             players: vec![(
                 Snake::new(
                     VecDeque::from(vec![
@@ -55,12 +226,16 @@ mod tests {
                 Uuid::new_v4(),
                 "Bartek".into(),
                 123,
+                false,
+                None,
             )],
             food: vec![
                 Point { x: 1, y: 2 },
                 Point { x: 1, y: 3 },
                 Point { x: 1, y: 5 },
             ],
+            power_ups: vec![(Point { x: 4, y: 4 }, PowerUp::Ghost)],
+            team_scores: vec![],
         };
 
         let serialized = serde_json::to_string(&msg);
@@ -0,0 +1,303 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::time::Duration;
+
+use super::world::DeathCause;
+
+/// Metrics of the whole process, served by the HTTP endpoint.
+pub static METRICS: Metrics = Metrics::new();
+
+/// Upper bounds in seconds of the tick duration histogram buckets.
+const TICK_BUCKETS: [f64; 9] = [0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25];
+
+/// Why a player's connection ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// Closed by the client
+    Closed,
+    /// Broken connection or invalid message
+    Error,
+    /// Refused while joining, e.g. full room or banned address
+    Rejected,
+    /// Dropped by the room, after a kick or a resume on another connection
+    Removed,
+    /// Kept sending too fast after being warned, spectators included
+    RateLimited,
+}
+
+impl DisconnectReason {
+    const ALL: [DisconnectReason; 5] = [
+        DisconnectReason::Closed,
+        DisconnectReason::Error,
+        DisconnectReason::Rejected,
+        DisconnectReason::Removed,
+        DisconnectReason::RateLimited,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            DisconnectReason::Closed => "closed",
+            DisconnectReason::Error => "error",
+            DisconnectReason::Rejected => "rejected",
+            DisconnectReason::Removed => "removed",
+            DisconnectReason::RateLimited => "rate_limited",
+        }
+    }
+}
+
+const DEATH_CAUSES: [&str; 4] = ["wall", "itself", "snake", "head_on"];
+
+fn death_cause_index(cause: &DeathCause) -> usize {
+    match cause {
+        DeathCause::Wall => 0,
+        DeathCause::Itself => 1,
+        DeathCause::Snake { .. } => 2,
+        DeathCause::HeadOn => 3,
+    }
+}
+
+/// Counters and gauges rendered in the Prometheus text format.
+pub struct Metrics {
+    connected_players: AtomicI64,
+    registrations: AtomicU64,
+    disconnects: [AtomicU64; DisconnectReason::ALL.len()],
+    deaths: [AtomicU64; DEATH_CAUSES.len()],
+    /// Ticks per bucket, not cumulative
+    tick_buckets: [AtomicU64; TICK_BUCKETS.len() + 1],
+    tick_micros: AtomicU64,
+    tick_overruns: AtomicU64,
+    rate_limit_warnings: AtomicU64,
+    sent_bytes: AtomicU64,
+    sent_messages: AtomicU64,
+}
+
+impl Metrics {
+    pub const fn new() -> Self {
+        Metrics {
+            connected_players: AtomicI64::new(0),
+            registrations: AtomicU64::new(0),
+            disconnects: [const { AtomicU64::new(0) }; DisconnectReason::ALL.len()],
+            deaths: [const { AtomicU64::new(0) }; DEATH_CAUSES.len()],
+            tick_buckets: [const { AtomicU64::new(0) }; TICK_BUCKETS.len() + 1],
+            tick_micros: AtomicU64::new(0),
+            tick_overruns: AtomicU64::new(0),
+            rate_limit_warnings: AtomicU64::new(0),
+            sent_bytes: AtomicU64::new(0),
+            sent_messages: AtomicU64::new(0),
+        }
+    }
+
+    pub fn player_connected(&self) {
+        self.connected_players.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn player_disconnected(&self) {
+        self.connected_players.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn registration(&self) {
+        self.registrations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn disconnect(&self, reason: DisconnectReason) {
+        self.disconnects[reason as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn death(&self, cause: &DeathCause) {
+        self.deaths[death_cause_index(cause)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records how long a game tick took, an overrun if it took longer than
+    /// the tick itself.
+    pub fn tick(&self, duration: Duration, game_tick: Duration) {
+        let seconds = duration.as_secs_f64();
+        let bucket = TICK_BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(TICK_BUCKETS.len());
+        self.tick_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.tick_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+        if duration > game_tick {
+            self.tick_overruns.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn rate_limit_warning(&self) {
+        self.rate_limit_warnings.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn sent(&self, bytes: usize) {
+        self.sent_messages.fetch_add(1, Ordering::Relaxed);
+        self.sent_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        header(
+            &mut out,
+            "snake_connected_players",
+            "gauge",
+            "Players with an open connection",
+        );
+        _ = writeln!(
+            out,
+            "snake_connected_players {}",
+            self.connected_players.load(Ordering::Relaxed)
+        );
+
+        header(
+            &mut out,
+            "snake_registrations_total",
+            "counter",
+            "Players registered",
+        );
+        _ = writeln!(
+            out,
+            "snake_registrations_total {}",
+            self.registrations.load(Ordering::Relaxed)
+        );
+
+        header(
+            &mut out,
+            "snake_disconnects_total",
+            "counter",
+            "Player connections ended, by reason",
+        );
+        for reason in DisconnectReason::ALL {
+            _ = writeln!(
+                out,
+                "snake_disconnects_total{{reason=\"{}\"}} {}",
+                reason.label(),
+                self.disconnects[reason as usize].load(Ordering::Relaxed)
+            );
+        }
+
+        header(
+            &mut out,
+            "snake_deaths_total",
+            "counter",
+            "Snakes killed, by cause",
+        );
+        for (cause, count) in DEATH_CAUSES.iter().zip(&self.deaths) {
+            _ = writeln!(
+                out,
+                "snake_deaths_total{{cause=\"{}\"}} {}",
+                cause,
+                count.load(Ordering::Relaxed)
+            );
+        }
+
+        header(
+            &mut out,
+            "snake_tick_duration_seconds",
+            "histogram",
+            "Time spent on a game tick",
+        );
+        let mut cumulative = 0;
+        for (i, count) in self.tick_buckets.iter().enumerate() {
+            cumulative += count.load(Ordering::Relaxed);
+            let bound = TICK_BUCKETS
+                .get(i)
+                .map_or("+Inf".to_string(), |bound| bound.to_string());
+            _ = writeln!(
+                out,
+                "snake_tick_duration_seconds_bucket{{le=\"{}\"}} {}",
+                bound, cumulative
+            );
+        }
+        _ = writeln!(
+            out,
+            "snake_tick_duration_seconds_sum {}",
+            self.tick_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0
+        );
+        _ = writeln!(out, "snake_tick_duration_seconds_count {}", cumulative);
+
+        header(
+            &mut out,
+            "snake_tick_overruns_total",
+            "counter",
+            "Ticks that took longer than the game tick",
+        );
+        _ = writeln!(
+            out,
+            "snake_tick_overruns_total {}",
+            self.tick_overruns.load(Ordering::Relaxed)
+        );
+
+        header(
+            &mut out,
+            "snake_rate_limit_warnings_total",
+            "counter",
+            "Incoming messages dropped with a warning for exceeding the rate limit",
+        );
+        _ = writeln!(
+            out,
+            "snake_rate_limit_warnings_total {}",
+            self.rate_limit_warnings.load(Ordering::Relaxed)
+        );
+
+        header(
+            &mut out,
+            "snake_sent_messages_total",
+            "counter",
+            "Websocket messages sent",
+        );
+        _ = writeln!(
+            out,
+            "snake_sent_messages_total {}",
+            self.sent_messages.load(Ordering::Relaxed)
+        );
+
+        header(
+            &mut out,
+            "snake_sent_bytes_total",
+            "counter",
+            "Encoded bytes of websocket messages sent",
+        );
+        _ = writeln!(
+            out,
+            "snake_sent_bytes_total {}",
+            self.sent_bytes.load(Ordering::Relaxed)
+        );
+
+        out
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    _ = writeln!(out, "# HELP {} {}", name, help);
+    _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_text_format() {
+        let metrics = Metrics::new();
+        metrics.death(&DeathCause::HeadOn);
+        metrics.disconnect(DisconnectReason::Closed);
+        metrics.tick(Duration::from_millis(2), Duration::from_millis(100));
+        metrics.tick(Duration::from_millis(200), Duration::from_millis(100));
+        metrics.sent(10);
+
+        let text = metrics.render();
+
+        assert!(text.contains("snake_deaths_total{cause=\"head_on\"} 1\n"));
+        assert!(text.contains("snake_disconnects_total{reason=\"closed\"} 1\n"));
+        assert!(text.contains("snake_tick_duration_seconds_bucket{le=\"0.001\"} 0\n"));
+        assert!(text.contains("snake_tick_duration_seconds_bucket{le=\"0.0025\"} 1\n"));
+        assert!(text.contains("snake_tick_duration_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(text.contains("snake_tick_duration_seconds_count 2\n"));
+        assert!(text.contains("snake_tick_overruns_total 1\n"));
+        assert!(text.contains("snake_sent_bytes_total 10\n"));
+    }
+}
@@ -1,35 +1,65 @@
+pub mod admin;
+pub mod bot;
+pub mod config;
 pub mod errors;
+pub mod http;
+pub mod leaderboard;
+pub mod map;
 pub mod messages;
+pub mod metrics;
+pub mod names;
+pub mod power_up;
+pub mod rate_limit;
+pub mod replay;
+pub mod room;
 pub mod snake;
 pub mod types;
+pub mod world;
 
-use clap::Parser;
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser};
 use dashmap::{DashMap, DashSet};
 use error_stack::{IntoReport, Report, Result, ResultExt};
 use futures::stream::{SplitSink, SplitStream};
 use futures_util::{SinkExt, StreamExt};
-use log::{debug, info};
-use rand::prelude::*;
-use rand_chacha::ChaCha20Rng;
-use std::collections::{HashMap, HashSet};
+use log::{debug, info, warn};
+use parking_lot::{Mutex, RwLock};
+use serde::{de::DeserializeOwned, Serialize};
+use std::ffi::OsString;
+use std::fmt::Display;
 use std::net::{IpAddr, Ipv4Addr};
-use std::sync::atomic::AtomicBool;
-use std::{net::SocketAddr, sync::Arc, time::Duration};
-use tokio::sync::mpsc::{channel, Receiver};
-use tokio::{
-    io::AsyncWriteExt,
-    net::{TcpListener, TcpStream},
-    time::sleep,
-};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{net::SocketAddr, sync::Arc};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::tungstenite::Error as WsError;
 use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
 use uuid::Uuid;
 
+use self::admin::{
+    token_matches, AdminCommand, AdminResponse, LastReload, ReloadReport, SettingsUpdate,
+};
+use self::bot::Difficulty;
+use self::config::Config;
+use self::http::{HttpResponse, ServerStatus};
+use self::leaderboard::Leaderboard;
+use self::map::Map;
+use self::metrics::{DisconnectReason, METRICS};
+use self::names::NamePolicy;
+use self::power_up::PowerUpWeights;
+use self::rate_limit::{RateLimit, RateLimiter, RateLimits};
+use self::replay::Replay;
+use self::room::{ClientHandle, GameMode, Room, RoomSettings, UpdateReceiver};
 use self::types::{
-    Colour, Direction, FieldHeightT, FieldWidthT, PlayerData, PlayerInfo, Point, State,
+    Colour, Direction, FieldHeightT, FieldWidthT, Point, ResumeToken, RoomId, TeamId, Wrap,
+    TEAM_COLOURS,
 };
 use self::{
     errors::*,
-    messages::{ClientMessage, ServerMessage},
+    messages::{ClientMessage, Encoding, ServerMessage},
 };
 
 #[derive(Parser, Debug)]
@@ -37,6 +67,14 @@ use self::{
 #[clap(author = "Bartek Sadlej <sadlejbartek@gmail.com>")]
 #[clap(version, about, long_about = None)]
 pub struct Args {
+    /// TOML file with defaults for the other options, reloaded on SIGHUP
+    #[clap(long, value_parser)]
+    config: Option<PathBuf>,
+
+    /// Command line the options were parsed from, kept for reloading
+    #[clap(skip)]
+    argv: Vec<OsString>,
+
     /// Server IP address to use
     #[clap(short='a', value_parser, default_value_t=IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)))]
     address: IpAddr,
@@ -44,6 +82,10 @@ pub struct Args {
     #[clap(short = 'p', value_parser, default_value_t = 43210)]
     port: u16,
 
+    /// Port of the HTTP endpoint serving /status, /players and /metrics, disabled when missing
+    #[clap(long, value_parser)]
+    http_port: Option<u16>,
+
     /// Maximum players count
     #[clap(short = 'c', value_parser, default_value_t = 25)]
     max_players_count: usize,
@@ -56,6 +98,14 @@ pub struct Args {
     #[clap(short = 'h', value_parser, default_value_t = 20)]
     field_height: FieldHeightT,
 
+    /// Field edges snakes wrap around instead of dying
+    #[clap(long, value_enum, default_value_t = Wrap::None)]
+    wrap: Wrap,
+
+    /// Map file with walls, spawn points and food cells, sets the field size
+    #[clap(long, value_parser = Map::from_arg, conflicts_with_all = &["field-width", "field-height"])]
+    map: Option<Map>,
+
     /// Game tick in miliseconds
     #[clap(short = 't', value_parser, default_value_t = 100)]
     game_tick: u64,
@@ -63,35 +113,234 @@ pub struct Args {
     /// Food count on map
     #[clap(short = 'f', value_parser, default_value_t = 10)]
     food_count: usize,
-}
 
-pub struct Server {
-    args: Args,
-    state: Arc<State>,
+    /// Number of teams players are split into, 0 disables teams
+    #[clap(long, value_parser = clap::value_parser!(u8).range(0..=TEAM_COLOURS.len() as i64), default_value_t = 0)]
+    teams: TeamId,
+
+    /// Whether running into a teammate's body kills
+    #[clap(long, action = clap::ArgAction::Set, default_value_t = true)]
+    friendly_fire: bool,
+
+    /// Endless game or rounds of last snake standing
+    #[clap(long, value_enum, default_value_t = GameMode::Endless)]
+    mode: GameMode,
+
+    /// Seconds the lobby counts down before a round starts
+    #[clap(long, value_parser, default_value_t = 10)]
+    lobby_countdown: u64,
+
+    /// Power-ups kept on the field
+    #[clap(long, value_parser, default_value_t = 0)]
+    power_ups: usize,
+
+    /// Relative spawn chance per power-up, e.g. ghost=3,shrink=0
+    #[clap(long, value_parser = PowerUpWeights::from_arg, default_value = "speed-boost=1,shrink=1,ghost=1,score-multiplier=1")]
+    power_up_weights: PowerUpWeights,
+
+    /// Bots added to every room
+    #[clap(long, value_parser, default_value_t = 0)]
+    bots: usize,
+
+    /// How well bots play
+    #[clap(long, value_enum, default_value_t = Difficulty::Normal)]
+    bot_difficulty: Difficulty,
+
+    /// Sub-ticks between a bot's moves, e.g. twice `--sub-ticks` for bots at
+    /// half speed. 0 moves bots once per tick like players
+    #[clap(long, value_parser, default_value_t = 0)]
+    bot_move_interval: u32,
+
+    /// Turns a player can queue ahead, one is applied per tick
+    #[clap(long, value_parser, default_value_t = 3)]
+    input_queue_len: usize,
+
+    /// Steps each game tick is split into, snakes move once per tick unless
+    /// sped up or slowed down
+    #[clap(long, value_parser, default_value_t = 2)]
+    sub_ticks: u32,
+
+    /// Seconds a disconnected player's snake waits for the session to be resumed, 0 disables
+    #[clap(long, value_parser, default_value_t = 10)]
+    resume_grace: u64,
+
+    /// Ticks between full state updates sent to delta clients
+    #[clap(long, value_parser, default_value_t = 50)]
+    keyframe_interval: u64,
+
+    /// Directory to record every match into
+    #[clap(long, value_parser)]
+    record_dir: Option<PathBuf>,
+
+    /// Replay a recorded match to every client instead of hosting a game
+    #[clap(long, value_parser)]
+    replay: Option<PathBuf>,
+
+    /// File keeping the best scores across restarts, kept in memory only when missing
+    #[clap(long, value_parser)]
+    leaderboard: Option<PathBuf>,
+
+    /// Players listed in leaderboard messages
+    #[clap(long, value_parser, default_value_t = 10)]
+    leaderboard_size: usize,
+
+    /// Seconds between leaderboard pushes to every client, 0 disables
+    #[clap(long, value_parser, default_value_t = 30)]
+    leaderboard_interval: u64,
+
+    /// Characters a chat message is cut to
+    #[clap(long, value_parser, default_value_t = 200)]
+    chat_max_len: usize,
+
+    /// Milliseconds a player has to wait between chat messages
+    #[clap(long, value_parser, default_value_t = 1000)]
+    chat_interval: u64,
+
+    /// Recent chat messages sent to players joining a room
+    #[clap(long, value_parser, default_value_t = 20)]
+    chat_history: usize,
+
+    /// Messages per second a connection may send, 0 disables
+    #[clap(long, value_parser, default_value_t = 20)]
+    rate_limit_messages: u32,
+
+    /// Bytes per second a connection may send, 0 disables
+    #[clap(long, value_parser, default_value_t = 16384)]
+    rate_limit_bytes: u32,
+
+    /// Messages dropped with a warning before a connection sending too fast is closed
+    #[clap(long, value_parser, default_value_t = 3)]
+    rate_limit_warnings: u32,
+
+    /// Fewest characters a player name may have
+    #[clap(long, value_parser, default_value_t = 1)]
+    name_min_len: usize,
+
+    /// Most characters a player name may have
+    #[clap(long, value_parser, default_value_t = 16)]
+    name_max_len: usize,
+
+    /// File with words player names must not contain, one per line
+    #[clap(long, value_parser)]
+    banned_names: Option<PathBuf>,
+
+    /// Token admin connections authenticate with, admin connections are refused when missing
+    #[clap(long, value_parser)]
+    admin_token: Option<String>,
 }
 
-impl State {
-    fn new(args: &Args) -> Self {
-        State {
-            players: DashMap::with_capacity(args.max_players_count),
-            map_state: DashMap::with_capacity(
-                args.field_height as usize * args.field_width as usize,
-            ),
-            is_running: AtomicBool::new(false),
-            food: DashSet::new(),
+impl Args {
+    /// Parses the command line, taking options not given there from the
+    /// `--config` file, and checks the resulting game settings.
+    pub fn load() -> Result<Self, ConfigError> {
+        let argv: Vec<OsString> = std::env::args_os().collect();
+        let matches = Args::command().get_matches_from(&argv);
+        Args::layered(&matches, argv)
+    }
+
+    /// Parses the command line the options were loaded from again, reading
+    /// the current `--config` file.
+    fn reload(&self) -> Result<Self, ConfigError> {
+        let matches = Args::command()
+            .try_get_matches_from(&self.argv)
+            .report()
+            .change_context(ConfigError)?;
+        Args::layered(&matches, self.argv.clone())
+    }
+
+    fn layered(matches: &clap::ArgMatches, argv: Vec<OsString>) -> Result<Self, ConfigError> {
+        let mut args = Args::from_arg_matches(matches)
+            .report()
+            .change_context(ConfigError)?;
+        args.argv = argv;
+        if let Some(path) = args.config.clone() {
+            Config::load(&path)?.apply(&mut args, |id| {
+                matches.value_source(id) == Some(ValueSource::CommandLine)
+            })?;
         }
+        RoomSettings::from(&args)
+            .validate()
+            .map_err(|reason| Report::new(ConfigError).attach_printable(reason))?;
+        if args.name_min_len > args.name_max_len {
+            return Err(Report::new(ConfigError).attach_printable(format!(
+                "Name length limits {}..{} are empty",
+                args.name_min_len, args.name_max_len
+            )));
+        }
+        Ok(args)
     }
 }
 
+pub struct Server {
+    args: Args,
+    /// Settings new rooms are created with
+    settings: RwLock<RoomSettings>,
+    rooms: DashMap<RoomId, Arc<Room>>,
+    leaderboard: Arc<Leaderboard>,
+    names: NamePolicy,
+    /// Connection and address of connected players, for bans
+    addresses: DashMap<Uuid, (Uuid, IpAddr)>,
+    banned: DashSet<IpAddr>,
+    started: Instant,
+    last_reload: Mutex<Option<LastReload>>,
+}
+
 impl Server {
     pub fn new(args: Args) -> Self {
         Server {
-            state: Arc::new(State::new(&args)),
+            settings: RwLock::new(RoomSettings::from(&args)),
+            rooms: DashMap::new(),
+            leaderboard: Arc::new(Leaderboard::default()),
+            names: NamePolicy::from(&args),
+            addresses: DashMap::new(),
+            banned: DashSet::new(),
+            started: Instant::now(),
+            last_reload: Mutex::new(None),
             args,
         }
     }
 
     pub async fn run(self: &Arc<Self>) -> Result<(), ServerError> {
+        if let Some(path) = &self.args.leaderboard {
+            self.leaderboard
+                .open(path)
+                .change_context(ServerError)
+                .attach_printable("Unable to load leaderboard")?;
+        }
+
+        if let Some(path) = &self.args.banned_names {
+            self.names
+                .load_banned(path)
+                .change_context(ServerError)
+                .attach_printable("Unable to load banned names")?;
+        }
+
+        let replay = match &self.args.replay {
+            Some(path) => Some(Arc::new(
+                Replay::load(path)
+                    .change_context(ServerError)
+                    .attach_printable("Unable to load replay")?,
+            )),
+            None => None,
+        };
+
+        if let Some(port) = self.args.http_port {
+            let addr = format!("{}:{}", self.args.address, port);
+            let listener = TcpListener::bind(&addr).await.map_err(|e| {
+                Report::new(ServerError)
+                    .attach_printable(format!("Unable to start HTTP endpoint! {:?}", e))
+            })?;
+            info!("HTTP endpoint listening on {:?}", listener.local_addr());
+            let me = Arc::clone(self);
+            tokio::spawn(async move { me.serve_http(listener).await });
+        }
+
+        #[cfg(unix)]
+        {
+            let me = Arc::clone(self);
+            tokio::spawn(async move { me.reload_on_hangup().await });
+        }
+
         let addr = format!("{}:{}", self.args.address, self.args.port).to_string();
         let listener = TcpListener::bind(&addr).await.map_err(|e| {
             Report::new(ServerError).attach_printable(format!("Unable to start server! {:?}", e))
@@ -99,17 +348,18 @@ impl Server {
 
         info!("Server listening on {:?}", listener.local_addr());
 
-        self.refill_food();
-
         while let Ok((stream, addr)) = listener.accept().await {
             debug!("New connection from {}", addr);
 
             let me = Arc::clone(self);
+            let replay = replay.clone();
             tokio::spawn(async move {
-                if let Err(e) = me
-                    .handle_connection(stream, addr)
-                    .await
-                    .attach_printable_lazy(|| format!("Connection lost from {}", addr))
+                let result = match replay {
+                    Some(replay) => me.handle_replay_connection(stream, &replay).await,
+                    None => me.handle_connection(stream, addr).await,
+                };
+                if let Err(e) =
+                    result.attach_printable_lazy(|| format!("Connection lost from {}", addr))
                 {
                     debug!("{e:?}");
                 }
@@ -121,135 +371,777 @@ impl Server {
 
     async fn handle_connection(
         self: &Arc<Self>,
-        mut stream: TcpStream,
+        stream: TcpStream,
         addr: SocketAddr,
     ) -> Result<(), ConnectionError> {
-        if self.state.players.len() == self.args.max_players_count {
-            debug!("Connection limit reached. Disconnecting {}", addr);
-            _ = stream.shutdown().await;
+        let limits = RateLimits::from(&self.args);
+        let (stream, mut encoding) = Server::accept(stream, limits.websocket_config()).await?;
+        let (mut sink, mut stream) = stream.split();
+        if self.banned.contains(&addr.ip()) {
+            debug!("Refusing banned address {}", addr);
+            METRICS.disconnect(DisconnectReason::Rejected);
+            let reason = "Banned".to_string();
+            _ = Server::send_message(&mut sink, &ServerMessage::Error { reason }, encoding).await;
+            _ = sink.close().await;
             return Ok(());
         }
+        let mut limiter = RateLimiter::new(&limits);
+        let handshake = match stream.next().await {
+            Some(ws_msg) => match self
+                .limit_rate(&mut sink, &mut limiter, &ws_msg, &addr, encoding)
+                .await?
+            {
+                RateLimit::Allowed => Server::decode_message(ws_msg)?,
+                // The buckets start full, so this takes a message over the byte limit.
+                RateLimit::Warn => None,
+                RateLimit::Exceeded => {
+                    METRICS.disconnect(DisconnectReason::RateLimited);
+                    return Ok(());
+                }
+            },
+            None => None,
+        };
+        let joined = match handshake {
+            Some(ClientMessage::Admin { token }) => {
+                return self
+                    .handle_admin(sink, stream, limiter, addr, &token, encoding)
+                    .await;
+            }
+            Some(ClientMessage::Spectate {
+                room,
+                delta,
+                encoding: requested,
+            }) => {
+                let encoding = requested.unwrap_or(encoding);
+                return self
+                    .handle_spectator(sink, stream, limiter, room, delta, encoding)
+                    .await;
+            }
+            Some(ClientMessage::Resume {
+                token,
+                delta,
+                encoding: requested,
+            }) => {
+                debug!("Resuming session {}", token);
+                encoding = requested.unwrap_or(encoding);
+                self.resume_player(&token, delta)
+            }
+            Some(ClientMessage::Register {
+                name,
+                room,
+                delta,
+                encoding: requested,
+                team,
+            }) => {
+                debug!("New player name: {}, room: {}", name, room);
+                encoding = requested.unwrap_or(encoding);
+                self.names
+                    .clean(&name)
+                    .and_then(|name| self.join_player(&room, name, team, delta))
+            }
+            _ => Err("Expected Register, Spectate, Resume or Admin message".to_string()),
+        };
 
-        let stream = tokio_tungstenite::accept_async(stream).await.unwrap();
-        let (mut sink, mut stream) = stream.split();
-        let mut new_player_name: String = "Unknown".into();
-        if let Some(ClientMessage::Register { name }) = self.get_client_message(&mut stream).await?
-        {
-            debug!("New player name: {}", name);
-            new_player_name = name;
+        let (room, handle) = match joined {
+            Ok(joined) => joined,
+            Err(reason) => {
+                debug!("Disconnecting {}: {}", addr, reason);
+                METRICS.disconnect(DisconnectReason::Rejected);
+                _ = Server::send_message(&mut sink, &ServerMessage::Error { reason }, encoding)
+                    .await;
+                _ = sink.close().await;
+                return Ok(());
+            }
+        };
+        let uuid = handle.uuid;
+        debug!("Player uuid: {}", uuid);
+        self.addresses.insert(uuid, (handle.connection, addr.ip()));
+        let register = room.register_message(
+            uuid,
+            Some(handle.token),
+            handle.team,
+            Some(handle.name.clone()),
+        );
+        // The game loop removes the room once the player is gone.
+        self.start_game(&room);
+        METRICS.player_connected();
+        let rx = handle.rx;
+        let reason = async {
+            Server::send_message(&mut sink, &register, encoding)
+                .await
+                .change_context(ConnectionError)
+                .attach_printable("Unable to send Register message")?;
+            for message in room.chat_history() {
+                self.send_update(&mut sink, &message, encoding).await?;
+            }
+            self.player_loop(&room, sink, stream, limiter, uuid, rx, encoding)
+                .await
+        }
+        .await
+        .unwrap_or(DisconnectReason::Error);
+        METRICS.player_disconnected();
+        METRICS.disconnect(reason);
+        if reason == DisconnectReason::RateLimited {
+            // Resuming would start over with a full allowance.
+            room.kick(&uuid);
+        } else {
+            room.disconnect_player(&uuid, &handle.connection);
+        }
+        // Keeping the address of a connection resuming the session.
+        self.addresses
+            .remove_if(&uuid, |_, (connection, _)| *connection == handle.connection);
+
+        Ok(())
+    }
+
+    fn join_player(
+        self: &Arc<Self>,
+        room_id: &RoomId,
+        name: String,
+        team: Option<TeamId>,
+        delta: bool,
+    ) -> std::result::Result<(Arc<Room>, ClientHandle), String> {
+        // Checked before the room is looked up, a refused player must not
+        // create a room nobody is going to leave.
+        let teams = self.settings.read().teams;
+        if let Some(team) = team.filter(|team| teams > 0 && *team >= teams) {
+            return Err(format!("Team {} does not exist", team));
+        }
+        let (room, handle) = self.with_room(room_id, |room| {
+            if room.is_full() {
+                Err(format!("Room {} is full", room_id))
+            } else {
+                METRICS.registration();
+                Ok(room.spawn_player(name, self.names.max_len(), team, delta))
+            }
+        });
+        handle.map(|handle| (room, handle))
+    }
+
+    fn resume_player(
+        self: &Arc<Self>,
+        token: &ResumeToken,
+        delta: bool,
+    ) -> std::result::Result<(Arc<Room>, ClientHandle), String> {
+        let room_id = self
+            .rooms
+            .iter()
+            .find(|room| room.has_session(token))
+            .map(|room| room.id.clone());
+        // Holding the registry entry, so the room can not be torn down meanwhile.
+        let handle = room_id
+            .as_ref()
+            .and_then(|room_id| self.rooms.get(room_id))
+            .and_then(|room| Some((room.clone(), room.resume_player(token, delta)?)));
+        handle.ok_or_else(|| "Session expired".to_string())
+    }
+
+    async fn handle_spectator(
+        self: &Arc<Self>,
+        mut sink: SplitSink<WebSocketStream<TcpStream>, Message>,
+        mut stream: SplitStream<WebSocketStream<TcpStream>>,
+        mut limiter: RateLimiter,
+        room_id: RoomId,
+        delta: bool,
+        encoding: Encoding,
+    ) -> Result<(), ConnectionError> {
+        let (room, (uuid, mut rx)) = self.with_room(&room_id, |room| room.add_spectator(delta));
+        debug!("New spectator {} in room {}", uuid, room_id);
+        // The game loop removes the room once the spectator is gone.
+        self.start_game(&room);
+
+        let result = async {
+            let register = room.register_message(uuid, None, None, None);
+            Server::send_message(&mut sink, &register, encoding)
+                .await
+                .change_context(ConnectionError)
+                .attach_printable("Unable to send Register message")?;
+            for message in room.chat_history() {
+                self.send_update(&mut sink, &message, encoding).await?;
+            }
+            loop {
+                tokio::select! {
+                    Some(update) = rx.recv() => self.send_update(&mut sink, &update, encoding).await?,
+                    ws_msg = stream.next() => {
+                        let ws_msg = match ws_msg {
+                            Some(ws_msg) => ws_msg,
+                            None => return Ok(()),
+                        };
+                        match self.limit_rate(&mut sink, &mut limiter, &ws_msg, &uuid, encoding).await? {
+                            RateLimit::Allowed => (),
+                            RateLimit::Warn => continue,
+                            RateLimit::Exceeded => {
+                                METRICS.disconnect(DisconnectReason::RateLimited);
+                                return Ok(());
+                            }
+                        }
+                        match Server::decode_message(ws_msg)? {
+                            Some(ClientMessage::Turn { .. }) => (),
+                            Some(ClientMessage::Resync) => room.request_keyframe(&uuid),
+                            Some(ClientMessage::GetLeaderboard) => {
+                                self.send_update(&mut sink, &room.leaderboard_message(), encoding).await?
+                            }
+                            // Spectators have no name to chat with.
+                            Some(ClientMessage::Chat { .. }) => (),
+                            Some(_) => {
+                                return Err(ConnectionError)
+                                    .report()
+                                    .attach("Handshake message send twice!")
+                            }
+                            None => return Ok(()),
+                        }
+                    }
+                }
+            }
+        }
+        .await;
+        room.remove_spectator(&uuid);
+
+        result
+    }
+
+    /// Serves an operator connection until it is closed. Every
+    /// `AdminCommand` is answered with an `AdminResponse`.
+    async fn handle_admin(
+        self: &Arc<Self>,
+        mut sink: SplitSink<WebSocketStream<TcpStream>, Message>,
+        mut stream: SplitStream<WebSocketStream<TcpStream>>,
+        mut limiter: RateLimiter,
+        addr: SocketAddr,
+        token: &str,
+        encoding: Encoding,
+    ) -> Result<(), ConnectionError> {
+        let authorized = self
+            .args
+            .admin_token
+            .as_deref()
+            .is_some_and(|expected| token_matches(expected, token));
+        if !authorized {
+            warn!("Refused admin connection from {}", addr);
+            let reason = "Invalid admin token".to_string();
+            _ = Server::send_message(&mut sink, &ServerMessage::Error { reason }, encoding).await;
+            _ = sink.close().await;
+            return Ok(());
+        }
+        info!("Admin connected from {}", addr);
+
+        while let Some(ws_msg) = stream.next().await {
+            match self
+                .limit_rate(&mut sink, &mut limiter, &ws_msg, &addr, encoding)
+                .await?
+            {
+                RateLimit::Allowed => (),
+                RateLimit::Warn => continue,
+                RateLimit::Exceeded => return Ok(()),
+            }
+            let command: AdminCommand = match Server::decode_message(ws_msg)? {
+                Some(command) => command,
+                None => break,
+            };
+            info!("Admin command from {}: {:?}", addr, command);
+            let response = self.admin_command(command);
+            Server::send_message(&mut sink, &response, encoding)
+                .await
+                .change_context(ConnectionError)
+                .attach_printable("Could not send admin response")?;
+        }
+
+        Ok(())
+    }
+
+    fn admin_command(self: &Arc<Self>, command: AdminCommand) -> AdminResponse {
+        match command {
+            AdminCommand::ListPlayers => {
+                let mut players: Vec<_> = self
+                    .rooms
+                    .iter()
+                    .flat_map(|room| room.admin_players())
+                    .collect();
+                for player in &mut players {
+                    player.address = self.addresses.get(&player.uuid).map(|entry| entry.1);
+                }
+                AdminResponse::Players { players }
+            }
+            AdminCommand::Kick { uuid } => match self.kick(&uuid) {
+                true => AdminResponse::Ok,
+                false => AdminResponse::error(format!("Player {} not found", uuid)),
+            },
+            AdminCommand::Ban { uuid } => {
+                let ip = match self.addresses.get(&uuid) {
+                    Some(entry) => entry.1,
+                    None => {
+                        return AdminResponse::error(format!(
+                            "Player {} has no live connection, its address is unknown. Use BanAddress instead",
+                            uuid
+                        ))
+                    }
+                };
+                self.ban(ip);
+                AdminResponse::Ok
+            }
+            AdminCommand::BanAddress { address } => {
+                self.ban(address);
+                AdminResponse::Ok
+            }
+            AdminCommand::Pause { room } => self.set_paused(room.as_ref(), true),
+            AdminCommand::Resume { room } => self.set_paused(room.as_ref(), false),
+            AdminCommand::UpdateSettings(update) => {
+                if let Err(reason) = self.validate_update(&update) {
+                    return AdminResponse::Error { reason };
+                }
+                self.update_settings(&update);
+                AdminResponse::Ok
+            }
+            AdminCommand::ReloadConfig => match self.reload_config() {
+                Ok(report) => AdminResponse::Reloaded(report),
+                Err(e) => AdminResponse::error(format!("{:?}", e)),
+            },
+            AdminCommand::LastReload => AdminResponse::LastReload(self.last_reload.lock().clone()),
+        }
+    }
+
+    fn kick(self: &Arc<Self>, uuid: &Uuid) -> bool {
+        self.rooms.iter().any(|room| room.kick(uuid))
+    }
+
+    /// Refuses further connections from the address and kicks every player
+    /// connected from it.
+    fn ban(self: &Arc<Self>, ip: IpAddr) {
+        info!("Banning {}", ip);
+        self.banned.insert(ip);
+        let players: Vec<Uuid> = self
+            .addresses
+            .iter()
+            .filter(|entry| entry.value().1 == ip)
+            .map(|entry| *entry.key())
+            .collect();
+        for uuid in players {
+            self.kick(&uuid);
+        }
+    }
+
+    /// Pauses or resumes one room, or all of them when `room_id` is missing.
+    fn set_paused(self: &Arc<Self>, room_id: Option<&RoomId>, paused: bool) -> AdminResponse {
+        match room_id {
+            Some(room_id) => match self.rooms.get(room_id) {
+                Some(room) => room.set_paused(paused),
+                None => return AdminResponse::error(format!("Room {} not found", room_id)),
+            },
+            None => self.rooms.iter().for_each(|room| room.set_paused(paused)),
+        }
+        AdminResponse::Ok
+    }
+
+    fn update_settings(&self, update: &SettingsUpdate) {
+        self.settings.write().apply(update);
+        for room in self.rooms.iter() {
+            room.update_settings(update);
+        }
+    }
+
+    /// Reloads the config file, keeping the outcome for
+    /// `AdminCommand::LastReload`.
+    fn reload_config(&self) -> Result<ReloadReport, ConfigError> {
+        let result = self.apply_config();
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        *self.last_reload.lock() = Some(LastReload {
+            timestamp,
+            result: match &result {
+                Ok(report) => Ok(report.clone()),
+                Err(e) => Err(format!("{:?}", e)),
+            },
+        });
+        result
+    }
+
+    /// Applies settings changed in the config file that running rooms can
+    /// take, the others are reported as needing a restart.
+    fn apply_config(&self) -> Result<ReloadReport, ConfigError> {
+        let reloaded = self.args.reload()?;
+        let settings = RoomSettings::from(&reloaded);
+        let (applied, mut needs_restart) =
+            config::settings_changes(&self.settings.read(), &settings);
+
+        let args = &self.args;
+        let server_changes = [
+            ("address", args.address != reloaded.address),
+            ("port", args.port != reloaded.port),
+            ("http_port", args.http_port != reloaded.http_port),
+            ("record_dir", args.record_dir != reloaded.record_dir),
+            ("replay", args.replay != reloaded.replay),
+            ("leaderboard", args.leaderboard != reloaded.leaderboard),
+            ("admin_token", args.admin_token != reloaded.admin_token),
+            (
+                "name_limits",
+                (args.name_min_len, args.name_max_len)
+                    != (reloaded.name_min_len, reloaded.name_max_len),
+            ),
+            ("banned_names", args.banned_names != reloaded.banned_names),
+            (
+                "rate_limits",
+                RateLimits::from(args) != RateLimits::from(&reloaded),
+            ),
+        ];
+        needs_restart.extend(
+            server_changes
+                .into_iter()
+                .filter(|(_, changed)| *changed)
+                .map(|(name, _)| name.to_string()),
+        );
+
+        self.validate_update(&applied)
+            .map_err(|reason| Report::new(ConfigError).attach_printable(reason))?;
+        self.update_settings(&applied);
+        Ok(ReloadReport {
+            applied,
+            needs_restart,
+        })
+    }
+
+    /// Reloads the config file on every SIGHUP.
+    #[cfg(unix)]
+    async fn reload_on_hangup(self: &Arc<Self>) {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(hangups) => hangups,
+            Err(e) => return warn!("Unable to listen for SIGHUP: {}", e),
+        };
+        while hangups.recv().await.is_some() {
+            match self.reload_config() {
+                Ok(report) => {
+                    info!("Reloaded config, applied {:?}", report.applied);
+                    if !report.needs_restart.is_empty() {
+                        warn!("Changes to {:?} need a restart", report.needs_restart);
+                    }
+                }
+                Err(e) => warn!("Config not reloaded: {:?}", e),
+            }
+        }
+    }
+
+    fn validate_update(&self, update: &SettingsUpdate) -> std::result::Result<(), String> {
+        let mut settings = self.settings.read().clone();
+        settings.apply(update);
+        settings.validate()
+    }
+
+    async fn serve_http(self: &Arc<Self>, listener: TcpListener) {
+        while let Ok((stream, addr)) = listener.accept().await {
+            let me = Arc::clone(self);
+            tokio::spawn(async move {
+                if let Err(e) = http::serve_request(stream, |path| me.http_response(path)).await {
+                    debug!("HTTP request from {} failed: {:?}", addr, e);
+                }
+            });
         }
-        let (uuid, rx) = self.spawn_payer(new_player_name);
-        debug!("New player uuid: {}", uuid);
+    }
+
+    fn http_response(self: &Arc<Self>, path: &str) -> HttpResponse {
+        match path {
+            "/status" => HttpResponse::json(&self.status()),
+            "/players" => {
+                let players: Vec<_> = self
+                    .rooms
+                    .iter()
+                    .flat_map(|room| room.player_summaries())
+                    .collect();
+                HttpResponse::json(&players)
+            }
+            "/metrics" => {
+                HttpResponse::ok("text/plain; version=0.0.4; charset=utf-8", METRICS.render())
+            }
+            _ => HttpResponse::error(404),
+        }
+    }
+
+    fn status(self: &Arc<Self>) -> ServerStatus {
+        let settings = self.settings.read();
+        ServerStatus {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            uptime: self.started.elapsed().as_secs(),
+            field_width: settings.field_width,
+            field_height: settings.field_height,
+            game_tick: settings.game_tick,
+            player_count: self.rooms.iter().map(|room| room.human_count()).sum(),
+            max_players_count: settings.max_players_count,
+        }
+    }
+
+    /// Runs `f` on the room with given id, creating the room if needed. The
+    /// registry entry is held meanwhile, so the room can not be torn down
+    /// between lookup and joining it.
+    fn with_room<T>(
+        self: &Arc<Self>,
+        room_id: &RoomId,
+        f: impl FnOnce(&Arc<Room>) -> T,
+    ) -> (Arc<Room>, T) {
+        let room = self.rooms.entry(room_id.clone()).or_insert_with(|| {
+            info!("Creating room {}", room_id);
+            Arc::new(Room::new(
+                room_id.clone(),
+                self.settings.read().clone(),
+                self.args.record_dir.as_deref(),
+                self.leaderboard.clone(),
+            ))
+        });
+        let result = f(&room);
+        (room.clone(), result)
+    }
+
+    async fn handle_replay_connection(
+        self: &Arc<Self>,
+        stream: TcpStream,
+        replay: &Replay,
+    ) -> Result<(), ConnectionError> {
+        let limits = RateLimits::from(&self.args);
+        let (stream, encoding) = Server::accept(stream, limits.websocket_config()).await?;
+        let (mut sink, _) = stream.split();
+        let settings = &replay.header.settings;
         Server::send_message(
             &mut sink,
             &ServerMessage::Register {
-                field_width: self.args.field_width,
-                field_height: self.args.field_height,
-                uuid,
+                field_width: settings.field_width,
+                field_height: settings.field_height,
+                wrap: settings.wrap,
+                walls: settings.walls.clone(),
+                uuid: Uuid::nil(),
+                resume_token: None,
+                team: None,
+                name: None,
             },
+            encoding,
         )
         .await
         .change_context(ConnectionError)
         .attach_printable("Unable to send Register message")?;
-        self.start_game();
-        _ = self.player_loop(sink, stream, uuid, rx).await;
-        self.clear_player_parts(&uuid);
-        _ = self.state.players.remove(&uuid);
+
+        let mut replayer = replay.play();
+        loop {
+            tokio::time::sleep(Duration::from_millis(replayer.game_tick())).await;
+            let msg = match replayer.next_tick() {
+                Some(world) => ServerMessage::keyframe(world),
+                None => break,
+            };
+            Server::send_message(&mut sink, &msg, encoding)
+                .await
+                .change_context(ConnectionError)
+                .attach_printable("Could not send message to clinet")?;
+        }
+        debug!("Replay finished");
+        _ = sink.close().await;
 
         Ok(())
     }
 
-    fn start_game(self: &Arc<Self>) {
-        if self.state.is_running.compare_exchange(
-            false,
-            true,
-            std::sync::atomic::Ordering::SeqCst,
-            std::sync::atomic::Ordering::SeqCst,
-        ) == Ok(false)
-        {
-            debug!("RUNNING PLAYERS!");
+    fn start_game(self: &Arc<Self>, room: &Arc<Room>) {
+        if room.try_start() {
+            debug!("Starting game loop in room {}", room.id);
             let me = self.clone();
+            let room = room.clone();
             tokio::spawn(async move {
-                if let Err(e) = me.game_loop().await {
+                if let Err(e) = room.game_loop().await {
                     debug!("{:#?}", e);
                 }
+                me.remove_room_if_empty(&room);
             });
         }
     }
+
+    fn remove_room_if_empty(self: &Arc<Self>, room: &Arc<Room>) {
+        let removed = self.rooms.remove_if(&room.id, |_, registered| {
+            Arc::ptr_eq(registered, room) && registered.is_empty()
+        });
+        if removed.is_some() {
+            info!("Room {} is empty, removing", room.id);
+        } else if !room.is_empty() {
+            // Somebody joined after the loop has stopped.
+            self.start_game(room);
+        }
+    }
+
+    /// Accepts the websocket handshake, picking the message encoding from
+    /// the requested subprotocols. JSON is used when none is requested.
+    async fn accept(
+        stream: TcpStream,
+        config: WebSocketConfig,
+    ) -> Result<(WebSocketStream<TcpStream>, Encoding), ConnectionError> {
+        let mut negotiated = None;
+        // Signature is dictated by tungstenite.
+        #[allow(clippy::result_large_err)]
+        let callback = |request: &Request, mut response: Response| {
+            negotiated = request
+                .headers()
+                .get_all("Sec-WebSocket-Protocol")
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(','))
+                .find_map(Encoding::from_subprotocol);
+            if let Some(encoding) = negotiated {
+                response.headers_mut().insert(
+                    "Sec-WebSocket-Protocol",
+                    HeaderValue::from_static(encoding.subprotocol()),
+                );
+            }
+            Ok::<Response, ErrorResponse>(response)
+        };
+        let stream =
+            tokio_tungstenite::accept_hdr_async_with_config(stream, callback, Some(config))
+                .await
+                .report()
+                .change_context(ConnectionError)
+                .attach_printable("Websocket handshake failed")?;
+
+        Ok((stream, negotiated.unwrap_or_default()))
+    }
+
     async fn send_message(
         sink: &mut SplitSink<WebSocketStream<TcpStream>, Message>,
-        message: &ServerMessage,
+        message: &impl Serialize,
+        encoding: Encoding,
     ) -> Result<(), SendError> {
-        let encoded_message = serde_json::to_string(message)
-            .report()
-            .change_context(SendError)
-            .attach_printable("Serde error while encoding!")?;
+        let encoded_message = match encoding {
+            Encoding::Json => serde_json::to_string(message)
+                .report()
+                .change_context(SendError)
+                .attach_printable("Serde error while encoding!")
+                .map(Message::Text)?,
+            Encoding::MessagePack => rmp_serde::to_vec(message)
+                .report()
+                .change_context(SendError)
+                .attach_printable("MessagePack error while encoding!")
+                .map(Message::Binary)?,
+        };
 
-        sink.send(Message::Text(encoded_message))
+        let len = encoded_message.len();
+        sink.send(encoded_message)
             .await
             .report()
             .change_context(SendError)
             .attach_printable("Error while sending message!")?;
+        METRICS.sent(len);
 
         Ok(())
     }
 
-    async fn get_client_message(
+    /// Decodes a received websocket message, `None` if the client closed
+    /// the connection.
+    fn decode_message<T: DeserializeOwned>(
+        ws_msg: std::result::Result<Message, WsError>,
+    ) -> Result<Option<T>, ConnectionError> {
+        match ws_msg {
+            Ok(Message::Text(json_str)) => {
+                let message: T = serde_json::from_str(&json_str)
+                    .report()
+                    .change_context(ConnectionError)
+                    .attach_printable_lazy(|| format!("Invalid message body, got {}", json_str))?;
+
+                Ok(Some(message))
+            }
+            Ok(Message::Binary(bytes)) => {
+                let message: T = rmp_serde::from_slice(&bytes)
+                    .report()
+                    .change_context(ConnectionError)
+                    .attach_printable("Invalid MessagePack message body")?;
+
+                Ok(Some(message))
+            }
+            Ok(Message::Close(_)) => {
+                debug!("Connection closed");
+                Ok(None)
+            }
+            _ => Err(ConnectionError).report().attach("Invalid message"),
+        }
+    }
+
+    /// Checks a received message against the connection's rate limit. The
+    /// client is warned about dropped messages, and told why it is
+    /// disconnected once out of warnings.
+    async fn limit_rate(
         self: &Arc<Self>,
-        stream: &mut SplitStream<WebSocketStream<TcpStream>>,
-    ) -> Result<Option<ClientMessage>, ConnectionError> {
-        if let Some(ws_msg) = stream.next().await {
-            match ws_msg {
-                Ok(Message::Text(json_str)) => {
-                    let message: ClientMessage = serde_json::from_str(&json_str)
-                        .report()
-                        .change_context(ConnectionError)
-                        .attach_printable_lazy(|| {
-                            format!("Invalid message body, got {}", json_str)
-                        })?;
-
-                    Ok(Some(message))
-                }
-                _ => Err(ConnectionError).report().attach("Invalid message"),
+        sink: &mut SplitSink<WebSocketStream<TcpStream>, Message>,
+        limiter: &mut RateLimiter,
+        ws_msg: &std::result::Result<Message, WsError>,
+        client: &impl Display,
+        encoding: Encoding,
+    ) -> Result<RateLimit, ConnectionError> {
+        let len = ws_msg.as_ref().map_or(0, Message::len);
+        let verdict = limiter.check(len, Instant::now());
+        match verdict {
+            RateLimit::Allowed => (),
+            RateLimit::Warn => {
+                warn!("Dropping message of {}, sent too fast", client);
+                METRICS.rate_limit_warning();
+                let reason = "Sending too fast, messages are dropped".to_string();
+                self.send_update(sink, &ServerMessage::Warning { reason }, encoding)
+                    .await?;
+            }
+            RateLimit::Exceeded => {
+                warn!("Disconnecting {}, rate limit exceeded", client);
+                let reason = "Rate limit exceeded".to_string();
+                _ = Server::send_message(sink, &ServerMessage::Error { reason }, encoding).await;
+                _ = sink.close().await;
             }
-        } else {
-            debug!("Connection ended");
-            Ok(None)
         }
+        Ok(verdict)
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn player_loop(
         self: &Arc<Self>,
+        room: &Arc<Room>,
         mut sink: SplitSink<WebSocketStream<TcpStream>, Message>,
         mut stream: SplitStream<WebSocketStream<TcpStream>>,
+        mut limiter: RateLimiter,
         uuid: Uuid,
-        mut rx: Receiver<()>,
-    ) -> Result<(), ConnectionError> {
+        mut rx: UpdateReceiver,
+        encoding: Encoding,
+    ) -> Result<DisconnectReason, ConnectionError> {
         loop {
             tokio::select! {
-            _ = rx.recv() => {
-                self.send_turn_message(&mut sink).await?
-            }
-            client_message = self.get_client_message(&mut stream) => {
-                match client_message {
+            update = rx.recv() => match update {
+                Some(update) => self.send_update(&mut sink, &update, encoding).await?,
+                // Session was resumed on another connection.
+                None => return Ok(DisconnectReason::Removed),
+            },
+            ws_msg = stream.next() => {
+                let ws_msg = match ws_msg {
+                    Some(ws_msg) => ws_msg,
+                    None => return Ok(DisconnectReason::Closed),
+                };
+                match self.limit_rate(&mut sink, &mut limiter, &ws_msg, &uuid, encoding).await? {
+                    RateLimit::Allowed => (),
+                    RateLimit::Warn => continue,
+                    RateLimit::Exceeded => return Ok(DisconnectReason::RateLimited),
+                }
+                match Server::decode_message(ws_msg) {
                     Ok(Some(message)) => match message {
                         ClientMessage::Turn { direction } => {
-                                if let Some(mut player_state) = self.state.players.get_mut(&uuid) {
-                                    player_state.last_move = Some(direction);
-                                } else {
+                                if !room.push_input(uuid, direction) {
                                     return Err(ConnectionError)
                                     .report()
                                     .attach("Game logic broken! Player not in players.")
                                 }
                             },
-                            ClientMessage::Register { name: _ } => {
+                            ClientMessage::Resync => room.request_keyframe(&uuid),
+                            ClientMessage::GetLeaderboard => {
+                                self.send_update(&mut sink, &room.leaderboard_message(), encoding).await?
+                            }
+                            ClientMessage::Chat { text } => room.chat(uuid, &text),
+                            ClientMessage::Register { .. }
+                            | ClientMessage::Spectate { .. }
+                            | ClientMessage::Resume { .. }
+                            | ClientMessage::Admin { .. } => {
                                         return Err(ConnectionError)
                                         .report()
-                                        .attach("Register message send twice!")
+                                        .attach("Handshake message send twice!")
 
                             }
                         }
-                    Ok(None) => return Ok(()),
+                    Ok(None) => return Ok(DisconnectReason::Closed),
                     Err(e) => return Err(e),
                     }
                 }
@@ -257,151 +1149,17 @@ impl Server {
         }
     }
 
-    async fn send_turn_message(
+    async fn send_update(
         self: &Arc<Self>,
         sink: &mut SplitSink<WebSocketStream<TcpStream>, Message>,
+        update: &ServerMessage,
+        encoding: Encoding,
     ) -> Result<(), ConnectionError> {
-        let players: Vec<PlayerInfo> = self
-            .state
-            .players
-            .iter()
-            .map(|entry| {
-                (
-                    entry.value().snake.clone(),
-                    *entry.key(),
-                    entry.value().name.clone(),
-                    entry.value().score,
-                )
-            })
-            .collect();
-        let food = self.state.food.iter().map(|entry| *entry.key()).collect();
-        let msg = ServerMessage::Turn { players, food };
-        Server::send_message(sink, &msg)
+        Server::send_message(sink, update, encoding)
             .await
             .change_context(ConnectionError)
             .attach_printable("Could not send message to clinet")?;
 
         Ok(())
     }
-
-    fn spawn_payer(self: &Arc<Self>, name: String) -> (Uuid, Receiver<()>) {
-        let mut rng = ChaCha20Rng::from_entropy();
-
-        let uuid = Uuid::new_v4();
-        assert!(!self.state.players.contains_key(&uuid));
-        let colour: Colour = rng.gen();
-        let direction: Direction = rng.gen();
-        let (tx, rx) = channel::<()>(16);
-        let starting_point: Point = self.random_free_point();
-        let new_player = PlayerData::new(name, starting_point, colour, direction, tx);
-
-        self.state.players.insert(uuid, new_player);
-
-        (uuid, rx)
-    }
-
-    async fn game_loop(self: &Arc<Self>) -> Result<(), GameError> {
-        while !self.state.players.is_empty() {
-            sleep(Duration::from_millis(self.args.game_tick)).await;
-
-            let mut killed_players = HashSet::<Uuid>::new();
-            let mut new_heads = HashMap::<Point, Vec<Uuid>>::new();
-
-            for mut player in self.state.players.iter_mut() {
-                if let Some(direction) = player.last_move {
-                    player.snake.set_direction(direction)
-                }
-                let (new_head, last) = player.value_mut().snake.do_move();
-                new_heads.entry(new_head).or_default().push(*player.key());
-                if self.state.food.remove(&new_head).is_some() {
-                    player.value_mut().score += 1;
-                } else {
-                    self.state.map_state.remove(&last);
-                    player.value_mut().snake.pop_last();
-                }
-            }
-
-            for new_head in new_heads.iter() {
-                if self.state.map_state.contains_key(new_head.0)
-                    || !self.is_in_map(new_head.0)
-                    || new_head.1.len() > 1
-                {
-                    killed_players.extend(new_head.1.clone());
-                } else {
-                    for player_uuid in new_head.1 {
-                        self.state.map_state.insert(*new_head.0, *player_uuid);
-                    }
-                }
-            }
-
-            for killed_player in killed_players {
-                let starting_point = self.random_free_point();
-                let direction = self.random_direction();
-                self.clear_player_parts(&killed_player);
-                if let Some(mut player_data) = self.state.players.get_mut(&killed_player) {
-                    player_data.killed_restart(starting_point, direction);
-                    player_data.score = 0;
-                }
-                self.state.map_state.insert(starting_point, killed_player);
-            }
-
-            self.refill_food();
-
-            for player in self.state.players.iter_mut() {
-                _ = player.tx.send(()).await;
-            }
-        }
-        debug!("NO PLAYERS STOP");
-        self.state
-            .is_running
-            .store(false, std::sync::atomic::Ordering::SeqCst);
-
-        Ok(())
-    }
-
-    fn random_free_point(self: &Arc<Self>) -> Point {
-        let mut rng = ChaCha20Rng::from_entropy();
-        let mut point: Point = rng.gen();
-        while !self.is_in_map(&point)
-            || self.state.map_state.contains_key(&point)
-            || self.state.food.contains(&point)
-        {
-            point = rng.gen();
-            point.x %= self.args.field_width;
-            point.y %= self.args.field_width;
-        }
-        point
-    }
-
-    fn random_direction(self: &Arc<Self>) -> Direction {
-        let mut rng = ChaCha20Rng::from_entropy();
-        rng.gen()
-    }
-
-    fn is_in_map(self: &Arc<Self>, point: &Point) -> bool {
-        let field_width = self.args.field_width;
-        let field_height = self.args.field_height;
-        match point {
-            Point { x, y } if *x < 0 || *y < 0 => false,
-            Point { x, y: _ } if *x >= field_width => false,
-            Point { x: _, y } if *y >= field_height => false,
-            _ => true,
-        }
-    }
-
-    fn refill_food(self: &Arc<Self>) {
-        let curr_food_count = self.state.food.len();
-        for _ in curr_food_count..self.args.food_count {
-            let food = self.random_free_point();
-            self.state.food.insert(food);
-        }
-    }
-
-    fn clear_player_parts(self: &Arc<Self>, uuid: &Uuid) {
-        if let Some(entry) = self.state.players.get_mut(uuid) {
-            for p in &entry.snake.parts {
-                self.state.map_state.remove(p);
-            }
-        }
-    }
 }
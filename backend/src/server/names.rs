@@ -0,0 +1,154 @@
+use error_stack::{IntoReport, Result, ResultExt};
+use parking_lot::RwLock;
+use std::path::Path;
+use unicode_normalization::UnicodeNormalization;
+
+use super::errors::ConfigError;
+use super::types::Name;
+use super::Args;
+
+/// Rules player names are checked against when registering.
+pub struct NamePolicy {
+    min_len: usize,
+    max_len: usize,
+    /// Lowercase words names must not contain
+    banned: RwLock<Vec<String>>,
+}
+
+impl From<&Args> for NamePolicy {
+    fn from(args: &Args) -> Self {
+        NamePolicy {
+            min_len: args.name_min_len,
+            max_len: args.name_max_len,
+            banned: RwLock::new(Vec::new()),
+        }
+    }
+}
+
+impl NamePolicy {
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    /// Reads banned words, one per line. Empty lines and lines starting with
+    /// `#` are skipped.
+    pub fn load_banned(&self, path: &Path) -> Result<(), ConfigError> {
+        let text = std::fs::read_to_string(path)
+            .report()
+            .change_context(ConfigError)
+            .attach_printable_lazy(|| format!("Unable to read {}", path.display()))?;
+        *self.banned.write() = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_lowercase)
+            .collect();
+        Ok(())
+    }
+
+    /// Normalizes the name to NFC and drops control characters and
+    /// surrounding whitespace. Returns the reason if the name is rejected.
+    pub fn clean(&self, name: &str) -> std::result::Result<Name, String> {
+        let name: String = name.nfc().filter(|c| !c.is_control()).collect();
+        let name = name.trim().to_string();
+
+        let len = name.chars().count();
+        if len < self.min_len || len > self.max_len {
+            return Err(format!(
+                "Name must be {} to {} characters long",
+                self.min_len, self.max_len
+            ));
+        }
+        let lowercase = name.to_lowercase();
+        if self
+            .banned
+            .read()
+            .iter()
+            .any(|word| lowercase.contains(word.as_str()))
+        {
+            return Err("Name is not allowed".to_string());
+        }
+
+        Ok(name)
+    }
+}
+
+/// Appends `#2`, `#3`, ... to the name if it is already taken, ignoring case.
+/// The name is cut so that it still fits in `max_len` characters with the
+/// suffix.
+pub fn unique_name<'a>(
+    name: Name,
+    max_len: usize,
+    taken: impl Iterator<Item = &'a str> + Clone,
+) -> Name {
+    let is_taken = |candidate: &str| {
+        taken
+            .clone()
+            .any(|other| other.to_lowercase() == candidate.to_lowercase())
+    };
+    if !is_taken(&name) {
+        return name;
+    }
+    (2..)
+        .map(|number| {
+            let suffix = format!("#{}", number);
+            let base: String = name
+                .chars()
+                .take(max_len.saturating_sub(suffix.chars().count()))
+                .collect();
+            format!("{}{}", base, suffix)
+        })
+        .find(|candidate| !is_taken(candidate))
+        .unwrap_or(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> NamePolicy {
+        NamePolicy {
+            min_len: 1,
+            max_len: 5,
+            banned: RwLock::new(vec!["darn".to_string()]),
+        }
+    }
+
+    #[test]
+    fn clean_names() {
+        let policy = policy();
+
+        assert_eq!(policy.clean(" Bob\u{7} ").unwrap(), "Bob");
+        // Decomposed é counts as one character.
+        assert_eq!(policy.clean("Rene\u{301}e").unwrap(), "Renée");
+        assert!(policy.clean(" \n ").is_err());
+        assert!(policy.clean("Robert").is_err());
+        assert!(policy.clean("DARNs").is_err());
+    }
+
+    #[test]
+    fn dedupe_names() {
+        let taken = ["Bob", "bob#2", "Alice"];
+
+        assert_eq!(unique_name("Eve".to_string(), 5, taken.into_iter()), "Eve");
+        assert_eq!(
+            unique_name("BOB".to_string(), 5, taken.into_iter()),
+            "BOB#3"
+        );
+    }
+
+    #[test]
+    fn dedupe_names_at_max_len() {
+        let taken = ["Alice", "ali#2"];
+
+        assert_eq!(
+            unique_name("alice".to_string(), 5, taken.into_iter()),
+            "ali#3"
+        );
+        let taken: Vec<String> = std::iter::once("Alice".to_string())
+            .chain((2..10).map(|number| format!("Ali#{}", number)))
+            .collect();
+        let name = unique_name("Alice".to_string(), 5, taken.iter().map(String::as_str));
+        assert_eq!(name, "Al#10");
+    }
+}
@@ -0,0 +1,145 @@
+use clap::ValueEnum;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use super::world::Tick;
+
+/// Ticks a timed power-up stays active after being picked up.
+pub const EFFECT_TICKS: Tick = 50;
+/// Points per food while `PowerUp::ScoreMultiplier` is active.
+pub const SCORE_MULTIPLIER: usize = 2;
+
+/// Pickup lying on the field next to plain food.
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, ValueEnum,
+)]
+pub enum PowerUp {
+    /// Halves the sub-ticks between the snake's moves
+    SpeedBoost,
+    /// Cuts the snake down to half its length at once
+    Shrink,
+    /// Lets the snake pass through other snakes and itself
+    Ghost,
+    /// Multiplies points for food
+    ScoreMultiplier,
+}
+
+impl PowerUp {
+    pub const ALL: [PowerUp; 4] = [
+        PowerUp::SpeedBoost,
+        PowerUp::Shrink,
+        PowerUp::Ghost,
+        PowerUp::ScoreMultiplier,
+    ];
+}
+
+/// Relative chance of each power-up being spawned.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PowerUpWeights {
+    pub speed_boost: u32,
+    pub shrink: u32,
+    pub ghost: u32,
+    pub score_multiplier: u32,
+}
+
+impl Default for PowerUpWeights {
+    fn default() -> Self {
+        PowerUpWeights {
+            speed_boost: 1,
+            shrink: 1,
+            ghost: 1,
+            score_multiplier: 1,
+        }
+    }
+}
+
+impl PowerUpWeights {
+    pub fn weight(&self, power_up: PowerUp) -> u32 {
+        match power_up {
+            PowerUp::SpeedBoost => self.speed_boost,
+            PowerUp::Shrink => self.shrink,
+            PowerUp::Ghost => self.ghost,
+            PowerUp::ScoreMultiplier => self.score_multiplier,
+        }
+    }
+
+    fn weight_mut(&mut self, power_up: PowerUp) -> &mut u32 {
+        match power_up {
+            PowerUp::SpeedBoost => &mut self.speed_boost,
+            PowerUp::Shrink => &mut self.shrink,
+            PowerUp::Ghost => &mut self.ghost,
+            PowerUp::ScoreMultiplier => &mut self.score_multiplier,
+        }
+    }
+
+    /// Draws a power-up, `None` when every weight is zero.
+    pub fn choose<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<PowerUp> {
+        let total: u32 = PowerUp::ALL.iter().map(|p| self.weight(*p)).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut roll = rng.gen_range(0..total);
+        for power_up in PowerUp::ALL {
+            let weight = self.weight(power_up);
+            if roll < weight {
+                return Some(power_up);
+            }
+            roll -= weight;
+        }
+        None
+    }
+
+    /// Value parser for the `--power-up-weights` command line option, e.g.
+    /// `ghost=3,shrink=0`. Power-ups not listed keep the default weight.
+    pub fn from_arg(arg: &str) -> std::result::Result<Self, String> {
+        let mut weights = PowerUpWeights::default();
+        for pair in arg
+            .split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+        {
+            let (name, weight) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("Expected <power-up>=<weight>, got '{}'", pair))?;
+            let power_up = PowerUp::from_str(name.trim(), true)?;
+            *weights.weight_mut(power_up) = weight
+                .trim()
+                .parse()
+                .map_err(|e| format!("Invalid weight for {}: {}", name, e))?;
+        }
+        Ok(weights)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_weights() {
+        let weights = PowerUpWeights::from_arg("ghost=3, shrink=0").unwrap();
+
+        assert_eq!(
+            weights,
+            PowerUpWeights {
+                ghost: 3,
+                shrink: 0,
+                ..Default::default()
+            }
+        );
+        assert!(PowerUpWeights::from_arg("teleport=1").is_err());
+        assert!(PowerUpWeights::from_arg("ghost").is_err());
+    }
+
+    #[test]
+    fn zero_weights_spawn_nothing() {
+        let weights = PowerUpWeights {
+            speed_boost: 0,
+            shrink: 0,
+            ghost: 0,
+            score_multiplier: 0,
+        };
+
+        assert_eq!(weights.choose(&mut rand::thread_rng()), None);
+    }
+}
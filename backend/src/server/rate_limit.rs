@@ -0,0 +1,184 @@
+use std::time::Instant;
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+
+use super::Args;
+
+/// Per connection limits on incoming messages, zero disables a limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimits {
+    pub messages_per_second: u32,
+    pub bytes_per_second: u32,
+    /// Messages dropped with a warning before the connection is closed
+    pub warnings: u32,
+}
+
+impl From<&Args> for RateLimits {
+    fn from(args: &Args) -> Self {
+        RateLimits {
+            messages_per_second: args.rate_limit_messages,
+            bytes_per_second: args.rate_limit_bytes,
+            warnings: args.rate_limit_warnings,
+        }
+    }
+}
+
+impl RateLimits {
+    /// Websocket limits refusing messages larger than a second worth of
+    /// bytes, which could never pass the byte limit anyway.
+    pub fn websocket_config(&self) -> WebSocketConfig {
+        let max_size = (self.bytes_per_second > 0).then_some(self.bytes_per_second as usize);
+        WebSocketConfig {
+            max_message_size: max_size.or(WebSocketConfig::default().max_message_size),
+            max_frame_size: max_size.or(WebSocketConfig::default().max_frame_size),
+            ..Default::default()
+        }
+    }
+}
+
+/// What to do with an incoming message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimit {
+    Allowed,
+    /// Drop the message and warn the client
+    Warn,
+    /// Drop the message and close the connection
+    Exceeded,
+}
+
+/// Holds up to one second worth of tokens, refilled continuously.
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+}
+
+impl TokenBucket {
+    fn new(rate: u32) -> Self {
+        TokenBucket {
+            rate: rate as f64,
+            tokens: rate as f64,
+        }
+    }
+
+    fn refill(&mut self, seconds: f64) {
+        self.tokens = (self.tokens + seconds * self.rate).min(self.rate);
+    }
+
+    fn is_full(&self) -> bool {
+        self.tokens >= self.rate
+    }
+
+    fn has(&self, amount: f64) -> bool {
+        self.rate == 0.0 || self.tokens >= amount
+    }
+
+    fn take(&mut self, amount: f64) {
+        if self.rate > 0.0 {
+            self.tokens -= amount;
+        }
+    }
+}
+
+/// Token buckets of a single connection.
+pub struct RateLimiter {
+    messages: TokenBucket,
+    bytes: TokenBucket,
+    warnings: u32,
+    /// Given back once the client has been quiet long enough to fill the
+    /// buckets again
+    warnings_left: u32,
+    last: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(limits: &RateLimits) -> Self {
+        RateLimiter {
+            messages: TokenBucket::new(limits.messages_per_second),
+            bytes: TokenBucket::new(limits.bytes_per_second),
+            warnings: limits.warnings,
+            warnings_left: limits.warnings,
+            last: Instant::now(),
+        }
+    }
+
+    /// Accounts a message of `len` bytes received at `now`. Messages over
+    /// the limit take no tokens.
+    pub fn check(&mut self, len: usize, now: Instant) -> RateLimit {
+        let seconds = now.saturating_duration_since(self.last).as_secs_f64();
+        self.last = self.last.max(now);
+        self.messages.refill(seconds);
+        self.bytes.refill(seconds);
+        if self.messages.is_full() && self.bytes.is_full() {
+            self.warnings_left = self.warnings;
+        }
+
+        if self.messages.has(1.0) && self.bytes.has(len as f64) {
+            self.messages.take(1.0);
+            self.bytes.take(len as f64);
+            RateLimit::Allowed
+        } else if self.warnings_left > 0 {
+            self.warnings_left -= 1;
+            RateLimit::Warn
+        } else {
+            RateLimit::Exceeded
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn warn_then_disconnect() {
+        let mut limiter = RateLimiter::new(&RateLimits {
+            messages_per_second: 2,
+            bytes_per_second: 0,
+            warnings: 1,
+        });
+        let start = limiter.last;
+
+        assert_eq!(limiter.check(10, start), RateLimit::Allowed);
+        assert_eq!(limiter.check(10, start), RateLimit::Allowed);
+        assert_eq!(limiter.check(10, start), RateLimit::Warn);
+        let later = start + Duration::from_millis(500);
+        assert_eq!(limiter.check(10, later), RateLimit::Allowed);
+        assert_eq!(limiter.check(10, later), RateLimit::Exceeded);
+    }
+
+    #[test]
+    fn warnings_come_back_after_quiet_period() {
+        let mut limiter = RateLimiter::new(&RateLimits {
+            messages_per_second: 1,
+            bytes_per_second: 0,
+            warnings: 1,
+        });
+        let start = limiter.last;
+
+        assert_eq!(limiter.check(10, start), RateLimit::Allowed);
+        assert_eq!(limiter.check(10, start), RateLimit::Warn);
+        let half = start + Duration::from_millis(500);
+        assert_eq!(limiter.check(10, half), RateLimit::Exceeded);
+        let later = start + Duration::from_secs(2);
+        assert_eq!(limiter.check(10, later), RateLimit::Allowed);
+        assert_eq!(limiter.check(10, later), RateLimit::Warn);
+    }
+
+    #[test]
+    fn limit_bytes() {
+        let mut limiter = RateLimiter::new(&RateLimits {
+            messages_per_second: 0,
+            bytes_per_second: 100,
+            warnings: 5,
+        });
+        let start = limiter.last;
+
+        assert_eq!(limiter.check(80, start), RateLimit::Allowed);
+        assert_eq!(limiter.check(80, start), RateLimit::Warn);
+        assert_eq!(limiter.check(20, start), RateLimit::Allowed);
+        assert_eq!(
+            limiter.check(80, start + Duration::from_secs(1)),
+            RateLimit::Allowed
+        );
+    }
+}
@@ -0,0 +1,280 @@
+use error_stack::{IntoReport, Result, ResultExt};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+use super::admin::SettingsUpdate;
+use super::bot::Difficulty;
+use super::errors::ReplayError;
+use super::room::RoomSettings;
+use super::types::{Direction, Name, RoomId, TeamId};
+use super::world::{Seed, World};
+
+/// First line of a replay file.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReplayHeader {
+    pub room: RoomId,
+    pub seed: Seed,
+    pub settings: RoomSettings,
+}
+
+/// Everything that changes the world, in the order it was applied.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ReplayEvent {
+    Join {
+        uuid: Uuid,
+        name: Name,
+        #[serde(default)]
+        bot: Option<Difficulty>,
+        #[serde(default)]
+        team: Option<TeamId>,
+    },
+    Leave {
+        uuid: Uuid,
+    },
+    Tick {
+        inputs: Vec<(Uuid, Direction)>,
+    },
+    RoundStart,
+    RoundOver,
+    /// Settings changed while running
+    Settings(SettingsUpdate),
+}
+
+/// Writes a match as JSON lines: the header followed by one event per line.
+pub struct Recorder {
+    writer: BufWriter<File>,
+}
+
+impl Recorder {
+    pub fn create(dir: &Path, header: &ReplayHeader) -> Result<Self, ReplayError> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        let room: String = header
+            .room
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let path: PathBuf = dir.join(format!("{}-{}.jsonl", room, timestamp));
+        let file = File::create(&path)
+            .report()
+            .change_context(ReplayError)
+            .attach_printable_lazy(|| format!("Unable to create {}", path.display()))?;
+
+        let mut recorder = Recorder {
+            writer: BufWriter::new(file),
+        };
+        recorder.write_line(header)?;
+
+        Ok(recorder)
+    }
+
+    pub fn record(&mut self, event: &ReplayEvent) -> Result<(), ReplayError> {
+        self.write_line(event)?;
+        if let ReplayEvent::Tick { .. } = event {
+            self.writer
+                .flush()
+                .report()
+                .change_context(ReplayError)
+                .attach_printable("Unable to flush replay file")?;
+        }
+        Ok(())
+    }
+
+    fn write_line<T: Serialize>(&mut self, value: &T) -> Result<(), ReplayError> {
+        serde_json::to_writer(&mut self.writer, value)
+            .report()
+            .change_context(ReplayError)
+            .attach_printable("Serde error while encoding!")?;
+        self.writer
+            .write_all(b"\n")
+            .report()
+            .change_context(ReplayError)
+            .attach_printable("Unable to write replay file")?;
+        Ok(())
+    }
+}
+
+/// Recorded match loaded back from a file.
+#[derive(Debug, Clone)]
+pub struct Replay {
+    pub header: ReplayHeader,
+    pub events: Vec<ReplayEvent>,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Self, ReplayError> {
+        let file = File::open(path)
+            .report()
+            .change_context(ReplayError)
+            .attach_printable_lazy(|| format!("Unable to open {}", path.display()))?;
+        let mut lines = BufReader::new(file).lines();
+
+        let header_line = lines
+            .next()
+            .ok_or(ReplayError)
+            .report()
+            .attach_printable("Replay file is empty")?
+            .report()
+            .change_context(ReplayError)?;
+        let header: ReplayHeader = serde_json::from_str(&header_line)
+            .report()
+            .change_context(ReplayError)
+            .attach_printable("Invalid replay header")?;
+
+        let mut events = Vec::new();
+        for (line_number, line) in lines.enumerate() {
+            let line = line.report().change_context(ReplayError)?;
+            if line.is_empty() {
+                continue;
+            }
+            let event: ReplayEvent = serde_json::from_str(&line)
+                .report()
+                .change_context(ReplayError)
+                .attach_printable_lazy(|| format!("Invalid event in line {}", line_number + 2))?;
+            events.push(event);
+        }
+
+        Ok(Replay { header, events })
+    }
+
+    pub fn play(&self) -> Replayer<'_> {
+        Replayer {
+            world: World::new((&self.header.settings).into(), self.header.seed),
+            settings: self.header.settings.clone(),
+            events: self.events.iter(),
+        }
+    }
+}
+
+/// Re-simulates a recorded match tick by tick.
+pub struct Replayer<'a> {
+    world: World,
+    settings: RoomSettings,
+    events: std::slice::Iter<'a, ReplayEvent>,
+}
+
+impl Replayer<'_> {
+    /// Milliseconds between the ticks played so far.
+    pub fn game_tick(&self) -> u64 {
+        self.settings.game_tick
+    }
+
+    /// Applies events up to and including the next tick, returns `None` when
+    /// the recording is over.
+    pub fn next_tick(&mut self) -> Option<&World> {
+        for event in self.events.by_ref() {
+            match event {
+                ReplayEvent::Join {
+                    uuid,
+                    name,
+                    bot,
+                    team,
+                } => {
+                    self.world.spawn(*uuid, name.clone(), *bot, *team);
+                }
+                ReplayEvent::Leave { uuid } => {
+                    self.world.remove_player(uuid);
+                }
+                ReplayEvent::RoundStart => self.world.start_round(),
+                ReplayEvent::RoundOver => {
+                    self.world.end_round();
+                }
+                ReplayEvent::Settings(update) => {
+                    self.settings.apply(update);
+                    if let Some(food_count) = update.food_count {
+                        self.world.set_food_count(food_count);
+                    }
+                }
+                ReplayEvent::Tick { inputs } => {
+                    self.world.step(inputs);
+                    return Some(&self.world);
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::messages::ServerMessage;
+    use crate::server::Args;
+    use clap::Parser;
+
+    fn snapshot(world: &World) -> String {
+        serde_json::to_string(&ServerMessage::keyframe(world)).unwrap()
+    }
+
+    #[test]
+    fn replay_reproduces_match() {
+        let settings = RoomSettings::from(&Args::parse_from(["backend", "-w", "12", "-h", "10"]));
+        let header = ReplayHeader {
+            room: "test".to_string(),
+            seed: 42,
+            settings: settings.clone(),
+        };
+        let dir = std::env::temp_dir().join(format!("replay-{}", Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+
+        let mut recorder = Recorder::create(&dir, &header).unwrap();
+        let mut world = World::new((&settings).into(), header.seed);
+        let mut snapshots = Vec::new();
+        let (a, b) = (Uuid::from_u128(1), Uuid::from_u128(2));
+        for (uuid, name) in [(a, "a"), (b, "b")] {
+            world.spawn(uuid, name.to_string(), None, None);
+            recorder
+                .record(&ReplayEvent::Join {
+                    uuid,
+                    name: name.to_string(),
+                    bot: None,
+                    team: None,
+                })
+                .unwrap();
+        }
+        for i in 0..40 {
+            if i == 20 {
+                let update = SettingsUpdate {
+                    game_tick: Some(50),
+                    food_count: Some(6),
+                    ..Default::default()
+                };
+                world.set_food_count(6);
+                recorder.record(&ReplayEvent::Settings(update)).unwrap();
+            }
+            let direction = [Direction::Left, Direction::Up, Direction::Right][i % 3];
+            let inputs = vec![(a, direction), (b, Direction::Down)];
+            world.step(&inputs);
+            recorder.record(&ReplayEvent::Tick { inputs }).unwrap();
+            snapshots.push(snapshot(&world));
+        }
+        drop(recorder);
+
+        let path = std::fs::read_dir(&dir)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap()
+            .path();
+        let replay = Replay::load(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let mut replayer = replay.play();
+        for expected in &snapshots {
+            assert_eq!(&snapshot(replayer.next_tick().unwrap()), expected);
+        }
+        assert!(replayer.next_tick().is_none());
+        assert_eq!(replayer.game_tick(), 50);
+    }
+}
@@ -0,0 +1,1061 @@
+use clap::ValueEnum;
+use dashmap::DashMap;
+use error_stack::Result;
+use log::{debug, warn};
+use parking_lot::{Mutex, RwLock, RwLockReadGuard};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::time::sleep;
+use uuid::Uuid;
+
+use super::admin::{AdminPlayerInfo, SettingsUpdate};
+use super::bot::Difficulty;
+use super::errors::GameError;
+use super::http::PlayerSummary;
+use super::leaderboard::Leaderboard;
+use super::map::Map;
+use super::messages::ServerMessage;
+use super::metrics::METRICS;
+use super::names::unique_name;
+use super::power_up::PowerUpWeights;
+use super::replay::{Recorder, ReplayEvent, ReplayHeader};
+use super::types::{
+    Direction, FieldHeightT, FieldWidthT, Name, Point, ResumeToken, RoomId, Score, TeamId, Wrap,
+};
+use super::world::{World, WorldSettings};
+use super::Args;
+
+/// How a room's game is played.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+pub enum GameMode {
+    /// Killed snakes respawn at once, the game never ends
+    #[default]
+    Endless,
+    /// Last snake standing wins, killed snakes wait for the next round
+    Rounds,
+}
+
+/// Per room game settings, by default taken from the command line `Args`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomSettings {
+    pub max_players_count: usize,
+    pub field_width: FieldWidthT,
+    pub field_height: FieldHeightT,
+    pub wrap: Wrap,
+    pub game_tick: u64,
+    pub food_count: usize,
+    pub keyframe_interval: u64,
+    pub resume_grace: u64,
+    pub input_queue_len: usize,
+    /// Steps a tick is split into, one for replays recorded before sub-ticks
+    #[serde(default = "one_sub_tick")]
+    pub sub_ticks: u32,
+    pub bots: usize,
+    pub bot_difficulty: Difficulty,
+    /// Sub-ticks between a bot's moves, 0 for one move per tick
+    #[serde(default)]
+    pub bot_move_interval: u32,
+    #[serde(default)]
+    pub walls: Vec<Point>,
+    #[serde(default)]
+    pub spawn_points: Vec<Point>,
+    #[serde(default)]
+    pub food_zones: Vec<Point>,
+    #[serde(default)]
+    pub power_up_count: usize,
+    #[serde(default)]
+    pub power_up_weights: PowerUpWeights,
+    #[serde(default)]
+    pub teams: TeamId,
+    #[serde(default)]
+    pub friendly_fire: bool,
+    #[serde(default)]
+    pub mode: GameMode,
+    /// Seconds between enough players being in the lobby and a round start
+    #[serde(default)]
+    pub lobby_countdown: u64,
+    #[serde(default)]
+    pub leaderboard_size: usize,
+    /// Seconds between leaderboard pushes, 0 disables them
+    #[serde(default)]
+    pub leaderboard_interval: u64,
+    /// Characters a chat message is cut to
+    #[serde(default)]
+    pub chat_max_len: usize,
+    /// Milliseconds a player has to wait between chat messages
+    #[serde(default)]
+    pub chat_interval: u64,
+    /// Chat messages sent to players joining
+    #[serde(default)]
+    pub chat_history: usize,
+}
+
+impl RoomSettings {
+    /// Checks settings a game can not be played with, the field must have
+    /// room for every player and bot.
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        if self.game_tick == 0 {
+            return Err("Game tick must be at least 1 ms".to_string());
+        }
+        WorldSettings::from(self).validate(self.max_players_count + self.bots)
+    }
+
+    pub fn apply(&mut self, update: &SettingsUpdate) {
+        if let Some(game_tick) = update.game_tick {
+            self.game_tick = game_tick;
+        }
+        if let Some(food_count) = update.food_count {
+            self.food_count = food_count;
+        }
+        if let Some(max_players_count) = update.max_players_count {
+            self.max_players_count = max_players_count;
+        }
+        if let Some(chat_max_len) = update.chat_max_len {
+            self.chat_max_len = chat_max_len;
+        }
+        if let Some(chat_interval) = update.chat_interval {
+            self.chat_interval = chat_interval;
+        }
+        if let Some(chat_history) = update.chat_history {
+            self.chat_history = chat_history;
+        }
+    }
+}
+
+fn one_sub_tick() -> u32 {
+    1
+}
+
+impl From<&Args> for RoomSettings {
+    fn from(args: &Args) -> Self {
+        let map = args.map.clone().unwrap_or_else(|| Map {
+            field_width: args.field_width,
+            field_height: args.field_height,
+            ..Default::default()
+        });
+        RoomSettings {
+            max_players_count: args.max_players_count,
+            field_width: map.field_width,
+            field_height: map.field_height,
+            wrap: args.wrap,
+            game_tick: args.game_tick,
+            food_count: args.food_count,
+            keyframe_interval: args.keyframe_interval.max(1),
+            resume_grace: args.resume_grace,
+            input_queue_len: args.input_queue_len,
+            sub_ticks: args.sub_ticks.max(1),
+            bots: args.bots,
+            bot_difficulty: args.bot_difficulty,
+            bot_move_interval: args.bot_move_interval,
+            walls: map.walls,
+            spawn_points: map.spawn_points,
+            food_zones: map.food_zones,
+            power_up_count: args.power_ups,
+            power_up_weights: args.power_up_weights,
+            teams: args.teams,
+            friendly_fire: args.friendly_fire,
+            mode: args.mode,
+            lobby_countdown: args.lobby_countdown,
+            leaderboard_size: args.leaderboard_size,
+            leaderboard_interval: args.leaderboard_interval,
+            chat_max_len: args.chat_max_len,
+            chat_interval: args.chat_interval,
+            chat_history: args.chat_history,
+        }
+    }
+}
+
+impl From<&RoomSettings> for WorldSettings {
+    fn from(settings: &RoomSettings) -> Self {
+        WorldSettings {
+            field_width: settings.field_width,
+            field_height: settings.field_height,
+            food_count: settings.food_count,
+            input_queue_len: settings.input_queue_len,
+            sub_ticks: settings.sub_ticks,
+            bot_move_interval: settings.bot_move_interval,
+            wrap: settings.wrap,
+            walls: settings.walls.clone(),
+            spawn_points: settings.spawn_points.clone(),
+            food_zones: settings.food_zones.clone(),
+            power_up_count: settings.power_up_count,
+            power_up_weights: settings.power_up_weights,
+            teams: settings.teams,
+            friendly_fire: settings.friendly_fire,
+            respawn: settings.mode == GameMode::Endless,
+        }
+    }
+}
+
+pub type UpdateReceiver = Receiver<Arc<ServerMessage>>;
+
+/// Connection receiving turn updates from the game loop.
+struct Client {
+    connection: Uuid,
+    tx: Sender<Arc<ServerMessage>>,
+    delta: bool,
+    needs_keyframe: bool,
+}
+
+impl Client {
+    fn new(delta: bool) -> (Self, UpdateReceiver) {
+        let (tx, rx) = channel(16);
+        let client = Client {
+            connection: Uuid::new_v4(),
+            tx,
+            delta,
+            needs_keyframe: true,
+        };
+        (client, rx)
+    }
+
+    fn wants_keyframe(&self) -> bool {
+        !self.delta || self.needs_keyframe
+    }
+
+    /// Update to send after a tick, `None` while the client waits for a
+    /// keyframe that was not built this tick.
+    fn update(
+        &mut self,
+        delta: &Arc<ServerMessage>,
+        keyframe: Option<&Arc<ServerMessage>>,
+        periodic: bool,
+    ) -> Option<Arc<ServerMessage>> {
+        match keyframe {
+            Some(keyframe) if periodic || self.wants_keyframe() => {
+                self.needs_keyframe = false;
+                Some(keyframe.clone())
+            }
+            // Joined after the keyframe was decided on.
+            None if self.wants_keyframe() => None,
+            _ => Some(delta.clone()),
+        }
+    }
+}
+
+/// Player's connection to a room.
+pub struct ClientHandle {
+    pub uuid: Uuid,
+    /// Tells this connection apart from a later one resuming the same player
+    pub connection: Uuid,
+    pub token: ResumeToken,
+    pub team: Option<TeamId>,
+    pub name: Name,
+    pub rx: UpdateReceiver,
+}
+
+/// Progress of a room in `GameMode::Rounds`.
+enum Phase {
+    /// Waiting for players, the round starts at the given time once there
+    /// are enough of them
+    Lobby {
+        starts_at: Option<Instant>,
+    },
+    Running,
+}
+
+/// Independent game arena with its own world and game loop.
+pub struct Room {
+    pub id: RoomId,
+    settings: RwLock<RoomSettings>,
+    world: Mutex<World>,
+    clients: DashMap<Uuid, Client>,
+    spectators: DashMap<Uuid, Client>,
+    /// Players without a connection, waiting to be resumed
+    detached: DashMap<Uuid, Instant>,
+    sessions: DashMap<ResumeToken, Uuid>,
+    inputs: Mutex<Vec<(Uuid, Direction)>>,
+    is_running: AtomicBool,
+    /// Game loop keeps running but the world is not stepped
+    paused: AtomicBool,
+    force_keyframe: AtomicBool,
+    recorder: Mutex<Option<Recorder>>,
+    phase: Mutex<Phase>,
+    leaderboard: Arc<Leaderboard>,
+    chat_history: Mutex<VecDeque<Arc<ServerMessage>>>,
+    last_chat: DashMap<Uuid, Instant>,
+}
+
+impl Room {
+    pub fn new(
+        id: RoomId,
+        settings: RoomSettings,
+        record_dir: Option<&Path>,
+        leaderboard: Arc<Leaderboard>,
+    ) -> Self {
+        let seed = rand::random();
+        let recorder = record_dir.and_then(|dir| {
+            let header = ReplayHeader {
+                room: id.clone(),
+                seed,
+                settings: settings.clone(),
+            };
+            Recorder::create(dir, &header)
+                .map_err(|e| warn!("Room {} will not be recorded: {:?}", id, e))
+                .ok()
+        });
+        let room = Room {
+            id,
+            world: Mutex::new(World::new((&settings).into(), seed)),
+            settings: RwLock::new(settings),
+            clients: DashMap::new(),
+            spectators: DashMap::new(),
+            detached: DashMap::new(),
+            sessions: DashMap::new(),
+            inputs: Mutex::new(Vec::new()),
+            is_running: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            force_keyframe: AtomicBool::new(false),
+            recorder: Mutex::new(recorder),
+            phase: Mutex::new(Phase::Lobby { starts_at: None }),
+            leaderboard,
+            chat_history: Mutex::new(VecDeque::new()),
+            last_chat: DashMap::new(),
+        };
+        room.spawn_bots();
+        room
+    }
+
+    fn spawn_bots(&self) {
+        let mut world = self.world.lock();
+        for number in 1..=self.settings().bots {
+            let uuid = Uuid::new_v4();
+            let name = format!("Bot {}", number);
+            let difficulty = self.settings().bot_difficulty;
+            world.spawn_bot(uuid, name.clone(), difficulty);
+            self.record(ReplayEvent::Join {
+                uuid,
+                name,
+                bot: Some(difficulty),
+                team: world.players()[&uuid].team,
+            });
+        }
+    }
+
+    pub fn settings(&self) -> RwLockReadGuard<'_, RoomSettings> {
+        self.settings.read()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty() && self.spectators.is_empty() && self.detached.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.human_count() >= self.settings().max_players_count
+    }
+
+    /// Marks room as running, returns false if game loop was already started.
+    pub fn try_start(&self) -> bool {
+        self.is_running
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    }
+
+    /// Adds a player to the world, in team mode to `team` if it exists. A
+    /// name already taken in the room gets a `#2`, `#3`, ... suffix, keeping
+    /// it within `name_max_len` characters.
+    pub fn spawn_player(
+        &self,
+        name: String,
+        name_max_len: usize,
+        team: Option<TeamId>,
+        delta: bool,
+    ) -> ClientHandle {
+        let uuid = Uuid::new_v4();
+        let token = ResumeToken::new_v4();
+        let (client, rx) = Client::new(delta);
+        let connection = client.connection;
+        let mut world = self.world.lock();
+        let name = unique_name(
+            name,
+            name_max_len,
+            world.players().values().map(|player| player.name.as_str()),
+        );
+        world.spawn(uuid, name.clone(), None, team);
+        let team = world.players()[&uuid].team;
+        self.record(ReplayEvent::Join {
+            uuid,
+            name: name.clone(),
+            bot: None,
+            team,
+        });
+        drop(world);
+        self.clients.insert(uuid, client);
+        self.sessions.insert(token, uuid);
+        self.force_keyframe.store(true, Ordering::SeqCst);
+
+        ClientHandle {
+            uuid,
+            connection,
+            token,
+            team,
+            name,
+            rx,
+        }
+    }
+
+    pub fn has_session(&self, token: &ResumeToken) -> bool {
+        self.sessions.contains_key(token)
+    }
+
+    /// Attaches a new connection to the player owning the token. A previous
+    /// connection of that player, if still open, stops getting updates.
+    pub fn resume_player(&self, token: &ResumeToken, delta: bool) -> Option<ClientHandle> {
+        let uuid = *self.sessions.get(token)?;
+        let (team, name) = self
+            .world
+            .lock()
+            .players()
+            .get(&uuid)
+            .map(|player| (player.team, player.name.clone()))?;
+        let (client, rx) = Client::new(delta);
+        let connection = client.connection;
+        self.clients.insert(uuid, client);
+        self.detached.remove(&uuid);
+
+        Some(ClientHandle {
+            uuid,
+            connection,
+            token: *token,
+            team,
+            name,
+            rx,
+        })
+    }
+
+    /// Called when player's connection ends. The snake stays in the game for
+    /// `resume_grace` seconds, unless the session was already resumed.
+    pub fn disconnect_player(&self, uuid: &Uuid, connection: &Uuid) {
+        if self
+            .clients
+            .remove_if(uuid, |_, client| client.connection == *connection)
+            .is_none()
+        {
+            return;
+        }
+        if self.settings().resume_grace > 0 {
+            debug!("Player {} detached from room {}", uuid, self.id);
+            self.detached.insert(*uuid, Instant::now());
+        } else {
+            self.remove_player(uuid);
+        }
+    }
+
+    fn remove_player(&self, uuid: &Uuid) {
+        self.clients.remove(uuid);
+        self.detached.remove(uuid);
+        self.sessions.retain(|_, player| player != uuid);
+        self.last_chat.remove(uuid);
+        let mut world = self.world.lock();
+        let player = world.remove_player(uuid);
+        self.record(ReplayEvent::Leave { uuid: *uuid });
+        drop(world);
+        self.force_keyframe.store(true, Ordering::SeqCst);
+        if let Some(player) = player.filter(|player| player.bot.is_none()) {
+            self.submit_score(&player.name, player.score, player.snake.parts.len());
+        }
+    }
+
+    fn remove_expired_players(&self) {
+        let grace = Duration::from_secs(self.settings().resume_grace);
+        let expired: Vec<Uuid> = self
+            .detached
+            .iter()
+            .filter(|entry| entry.value().elapsed() >= grace)
+            .map(|entry| *entry.key())
+            .collect();
+        for uuid in expired {
+            // Skipping players resumed in the meantime.
+            if self
+                .detached
+                .remove_if(&uuid, |_, since| since.elapsed() >= grace)
+                .is_some()
+            {
+                debug!("Session of player {} expired", uuid);
+                self.remove_player(&uuid);
+            }
+        }
+    }
+
+    /// Removes the player at once, without waiting for a resume. Returns
+    /// false if there is no such player.
+    pub fn kick(&self, uuid: &Uuid) -> bool {
+        if !self.world.lock().players().contains_key(uuid) {
+            return false;
+        }
+        if let Some((_, client)) = self.clients.remove(uuid) {
+            _ = client.tx.try_send(Arc::new(ServerMessage::Error {
+                reason: "Kicked".to_string(),
+            }));
+        }
+        self.remove_player(uuid);
+        true
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::SeqCst);
+    }
+
+    /// Changes settings of the running game, the update must be validated.
+    pub fn update_settings(&self, update: &SettingsUpdate) {
+        let mut world = self.world.lock();
+        self.settings.write().apply(update);
+        if let Some(food_count) = update.food_count {
+            world.set_food_count(food_count);
+        }
+        self.record(ReplayEvent::Settings(update.clone()));
+    }
+
+    /// Players of the room, without their addresses.
+    pub fn admin_players(&self) -> Vec<AdminPlayerInfo> {
+        self.world
+            .lock()
+            .players()
+            .iter()
+            .map(|(uuid, player)| AdminPlayerInfo {
+                room: self.id.clone(),
+                uuid: *uuid,
+                name: player.name.clone(),
+                score: player.score,
+                bot: player.bot.is_some(),
+                connected: player.bot.is_some() || self.clients.contains_key(uuid),
+                address: None,
+            })
+            .collect()
+    }
+
+    pub fn human_count(&self) -> usize {
+        self.world.lock().human_count()
+    }
+
+    pub fn player_summaries(&self) -> Vec<PlayerSummary> {
+        self.world
+            .lock()
+            .players()
+            .iter()
+            .map(|(uuid, player)| PlayerSummary {
+                room: self.id.clone(),
+                uuid: *uuid,
+                name: player.name.clone(),
+                score: player.score,
+                bot: player.bot.is_some(),
+                team: player.team,
+                alive: player.alive,
+            })
+            .collect()
+    }
+
+    /// Spectators get turn updates but have no snake in the world.
+    pub fn add_spectator(&self, delta: bool) -> (Uuid, UpdateReceiver) {
+        let uuid = Uuid::new_v4();
+        let (client, rx) = Client::new(delta);
+        self.spectators.insert(uuid, client);
+
+        (uuid, rx)
+    }
+
+    /// Next update sent to this client will be a full `ServerMessage::Turn`.
+    pub fn request_keyframe(&self, uuid: &Uuid) {
+        if let Some(mut client) = self.clients.get_mut(uuid) {
+            client.needs_keyframe = true;
+        } else if let Some(mut client) = self.spectators.get_mut(uuid) {
+            client.needs_keyframe = true;
+        }
+    }
+
+    pub fn remove_spectator(&self, uuid: &Uuid) {
+        self.spectators.remove(uuid);
+    }
+
+    pub fn push_input(&self, uuid: Uuid, direction: Direction) -> bool {
+        if !self.clients.contains_key(&uuid) {
+            return false;
+        }
+        self.inputs.lock().push((uuid, direction));
+        true
+    }
+
+    pub async fn game_loop(&self) -> Result<(), GameError> {
+        let leaderboard_interval = Duration::from_secs(self.settings().leaderboard_interval);
+        let mut last_leaderboard = Instant::now();
+        while !self.is_empty() {
+            let game_tick = self.settings().game_tick;
+            sleep(Duration::from_millis(game_tick)).await;
+            self.remove_expired_players();
+
+            if !leaderboard_interval.is_zero() && last_leaderboard.elapsed() >= leaderboard_interval
+            {
+                last_leaderboard = Instant::now();
+                self.broadcast(self.leaderboard_message()).await;
+            }
+
+            if self.paused.load(Ordering::SeqCst) {
+                // Turns sent while paused would all be applied at once.
+                self.inputs.lock().clear();
+                continue;
+            }
+
+            if self.settings().mode == GameMode::Rounds {
+                if let Some(lobby) = self.lobby_tick() {
+                    self.broadcast(lobby).await;
+                    continue;
+                }
+            }
+
+            let started = Instant::now();
+            let forced = self.force_keyframe.swap(false, Ordering::SeqCst);
+            let wants_keyframe = self
+                .clients
+                .iter()
+                .chain(self.spectators.iter())
+                .any(|client| client.wants_keyframe());
+
+            // Scores are submitted once the world is unlocked.
+            let mut scores = Vec::new();
+            let (delta, keyframe, periodic, round_over) = {
+                let inputs = std::mem::take(&mut *self.inputs.lock());
+                let mut world = self.world.lock();
+                let events = world.step(&inputs);
+                self.record(ReplayEvent::Tick { inputs });
+                for death in &events.deaths {
+                    METRICS.death(&death.cause);
+                    if let Some(player) = world.players().get(&death.uuid) {
+                        if player.bot.is_none() {
+                            scores.push((player.name.clone(), death.score, death.length));
+                        }
+                    }
+                }
+                let periodic = forced
+                    || events
+                        .tick
+                        .is_multiple_of(self.settings().keyframe_interval);
+                let keyframe =
+                    (wants_keyframe || periodic).then(|| Arc::new(ServerMessage::keyframe(&world)));
+                let round_over = (self.settings().mode == GameMode::Rounds
+                    && world.alive_count() <= 1)
+                    .then(|| self.end_round(&mut world, &mut scores));
+                (
+                    Arc::new(ServerMessage::delta(&events)),
+                    keyframe,
+                    periodic,
+                    round_over,
+                )
+            };
+            for (name, score, length) in scores {
+                self.submit_score(&name, score, length);
+            }
+
+            let mut updates = Vec::new();
+            for mut client in self.clients.iter_mut().chain(self.spectators.iter_mut()) {
+                if let Some(update) = client.update(&delta, keyframe.as_ref(), periodic) {
+                    updates.push((client.tx.clone(), update));
+                }
+            }
+            for (tx, update) in updates {
+                _ = tx.send(update).await;
+            }
+            if let Some(round_over) = round_over {
+                self.broadcast(round_over).await;
+            }
+            METRICS.tick(started.elapsed(), Duration::from_millis(game_tick));
+        }
+        debug!("Room {} has no clients, stopping", self.id);
+        self.is_running.store(false, Ordering::SeqCst);
+
+        Ok(())
+    }
+
+    /// Counts down to the next round while in the lobby. Returns the update
+    /// for clients, or `None` once the round is running.
+    fn lobby_tick(&self) -> Option<ServerMessage> {
+        let mut world = self.world.lock();
+        let mut phase = self.phase.lock();
+        let starts_at = match &mut *phase {
+            Phase::Running => return None,
+            Phase::Lobby { starts_at } => starts_at,
+        };
+
+        if world.players().len() < 2 {
+            *starts_at = None;
+            return Some(ServerMessage::Lobby { starts_in: None });
+        }
+        let start = *starts_at.get_or_insert_with(|| {
+            Instant::now() + Duration::from_secs(self.settings().lobby_countdown)
+        });
+        let now = Instant::now();
+        if now < start {
+            return Some(ServerMessage::Lobby {
+                starts_in: Some((start - now).as_millis() as u64),
+            });
+        }
+
+        debug!("Starting round in room {}", self.id);
+        world.start_round();
+        self.record(ReplayEvent::RoundStart);
+        *phase = Phase::Running;
+        self.force_keyframe.store(true, Ordering::SeqCst);
+        None
+    }
+
+    /// Adds the scores of surviving players to `scores`.
+    fn end_round(
+        &self,
+        world: &mut World,
+        scores: &mut Vec<(Name, Score, usize)>,
+    ) -> ServerMessage {
+        let winner = (world.alive_count() == 1)
+            .then(|| world.players().iter().find(|(_, player)| player.alive))
+            .flatten()
+            .map(|(uuid, _)| *uuid);
+        for player in world.players().values() {
+            if player.alive && player.bot.is_none() {
+                scores.push((player.name.clone(), player.score, player.snake.parts.len()));
+            }
+        }
+        let standings = world.end_round();
+        self.record(ReplayEvent::RoundOver);
+        debug!("Round over in room {}, winner {:?}", self.id, winner);
+        *self.phase.lock() = Phase::Lobby { starts_at: None };
+
+        ServerMessage::RoundOver { winner, standings }
+    }
+
+    pub fn register_message(
+        &self,
+        uuid: Uuid,
+        resume_token: Option<ResumeToken>,
+        team: Option<TeamId>,
+        name: Option<Name>,
+    ) -> ServerMessage {
+        let settings = self.settings();
+        ServerMessage::Register {
+            field_width: settings.field_width,
+            field_height: settings.field_height,
+            wrap: settings.wrap,
+            walls: settings.walls.clone(),
+            uuid,
+            resume_token,
+            team,
+            name,
+        }
+    }
+
+    pub fn leaderboard_message(&self) -> ServerMessage {
+        ServerMessage::Leaderboard {
+            entries: self.leaderboard.top(self.settings().leaderboard_size),
+        }
+    }
+
+    /// Must be called without the world locked.
+    fn submit_score(&self, name: &str, score: Score, length: usize) {
+        if self.leaderboard.submit(name, score, length) {
+            debug!("New best of {}: score {}, length {}", name, score, length);
+        }
+    }
+
+    /// Sends a player's chat message to everyone in the room. Messages sent
+    /// faster than `chat_interval` or empty after cleaning are dropped.
+    ///
+    /// Does not wait for full update channels, as the sender's own channel is
+    /// drained by the task calling this.
+    pub fn chat(&self, uuid: Uuid, text: &str) {
+        let text = match clean_chat_text(text, self.settings().chat_max_len) {
+            Some(text) => text,
+            None => return,
+        };
+        let interval = Duration::from_millis(self.settings().chat_interval);
+        if let Some(last) = self.last_chat.get(&uuid) {
+            if last.elapsed() < interval {
+                debug!("Dropping chat message of {}, sent too fast", uuid);
+                return;
+            }
+        }
+        let name = match self.world.lock().players().get(&uuid) {
+            Some(player) => player.name.clone(),
+            None => return,
+        };
+        self.last_chat.insert(uuid, Instant::now());
+
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        let message = Arc::new(ServerMessage::Chat {
+            from: uuid,
+            name,
+            text,
+            ts,
+        });
+        {
+            let mut history = self.chat_history.lock();
+            history.push_back(message.clone());
+            while history.len() > self.settings().chat_history {
+                history.pop_front();
+            }
+        }
+        for client in self.clients.iter().chain(self.spectators.iter()) {
+            _ = client.tx.try_send(message.clone());
+        }
+    }
+
+    /// Most recent chat messages, oldest first.
+    pub fn chat_history(&self) -> Vec<Arc<ServerMessage>> {
+        self.chat_history.lock().iter().cloned().collect()
+    }
+
+    /// Sends the message to every player and spectator.
+    async fn broadcast(&self, message: ServerMessage) {
+        let message = Arc::new(message);
+        let txs: Vec<_> = self
+            .clients
+            .iter()
+            .chain(self.spectators.iter())
+            .map(|client| client.tx.clone())
+            .collect();
+        for tx in txs {
+            _ = tx.send(message.clone()).await;
+        }
+    }
+
+    /// Must be called with the world locked, so events are written in the
+    /// order they were applied.
+    fn record(&self, event: ReplayEvent) {
+        let mut recorder = self.recorder.lock();
+        if let Some(writer) = recorder.as_mut() {
+            if let Err(e) = writer.record(&event) {
+                warn!("Stopping recording of room {}: {:?}", self.id, e);
+                *recorder = None;
+            }
+        }
+    }
+}
+
+/// Drops control characters and surrounding whitespace and cuts the text to
+/// `max_len` characters, `None` if nothing is left.
+fn clean_chat_text(text: &str, max_len: usize) -> Option<String> {
+    let text: String = text.chars().filter(|c| !c.is_control()).collect();
+    let text: String = text.trim().chars().take(max_len).collect();
+    (!text.is_empty()).then_some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::power_up::{PowerUp, SCORE_MULTIPLIER};
+    use clap::Parser;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    /// Snake parts and score of every player, the food and the power-ups.
+    type Board = (
+        BTreeMap<Uuid, (VecDeque<Point>, Score)>,
+        BTreeSet<Point>,
+        BTreeMap<Point, PowerUp>,
+    );
+
+    fn board(keyframe: &ServerMessage) -> Board {
+        match keyframe {
+            ServerMessage::Turn {
+                players,
+                food,
+                power_ups,
+                ..
+            } => (
+                players
+                    .iter()
+                    .map(|(snake, uuid, _, score, _, _)| (*uuid, (snake.parts.clone(), *score)))
+                    .collect(),
+                food.iter().copied().collect(),
+                power_ups.iter().copied().collect(),
+            ),
+            _ => panic!("Expected a keyframe, got {:?}", keyframe),
+        }
+    }
+
+    /// Applies a delta the way a client does, returns its deaths, spawned
+    /// food and the most points a single food was worth.
+    fn apply_delta((snakes, food, power_ups): &mut Board, delta: &ServerMessage) -> [usize; 3] {
+        match delta {
+            ServerMessage::Delta {
+                moves,
+                eaten,
+                picked_up,
+                shrunk,
+                deaths,
+                respawns,
+                spawned_food,
+                spawned_power_ups,
+                ..
+            } => {
+                for (uuid, head, tail) in moves {
+                    let (parts, _) = snakes.get_mut(uuid).unwrap();
+                    parts.push_front(*head);
+                    if tail.is_some() {
+                        parts.pop_back();
+                    }
+                }
+                let mut most_points = 0;
+                for (uuid, point, score) in eaten {
+                    food.remove(point);
+                    let (_, old_score) = snakes.get_mut(uuid).unwrap();
+                    most_points = most_points.max(score - *old_score);
+                    *old_score = *score;
+                }
+                for (_, point) in picked_up {
+                    power_ups.remove(point);
+                }
+                for (uuid, cut) in shrunk {
+                    let (parts, _) = snakes.get_mut(uuid).unwrap();
+                    parts.truncate(parts.len() - cut);
+                }
+                for uuid in deaths {
+                    snakes.remove(uuid);
+                }
+                for (uuid, point) in respawns {
+                    snakes.insert(*uuid, (VecDeque::from([*point]), 0));
+                }
+                food.extend(spawned_food);
+                power_ups.extend(spawned_power_ups.iter().copied());
+                [deaths.len(), spawned_food.len(), most_points]
+            }
+            _ => panic!("Expected a delta, got {:?}", delta),
+        }
+    }
+
+    #[test]
+    fn deltas_rebuild_next_keyframe() {
+        let settings = RoomSettings::from(&Args::parse_from([
+            "backend",
+            "-w",
+            "8",
+            "-h",
+            "6",
+            "-f",
+            "10",
+            "--power-ups",
+            "3",
+            "--power-up-weights",
+            "shrink=2,score-multiplier=4",
+        ]));
+        let mut world = World::new((&settings).into(), 3);
+        let players: Vec<Uuid> = (1..=3).map(Uuid::from_u128).collect();
+        for uuid in &players {
+            world.spawn(*uuid, uuid.to_string(), None, None);
+        }
+
+        let mut client = board(&ServerMessage::keyframe(&world));
+        let (mut deaths, mut spawned_food, mut most_points) = (0, 0, 0);
+        for i in 0..100 {
+            let inputs: Vec<_> = players
+                .iter()
+                .enumerate()
+                .map(|(k, uuid)| (*uuid, Direction::ALL[(i / 3 + k) % 4]))
+                .collect();
+            let events = world.step(&inputs);
+            let [died, spawned, points] = apply_delta(&mut client, &ServerMessage::delta(&events));
+            deaths += died;
+            spawned_food += spawned;
+            most_points = most_points.max(points);
+
+            assert_eq!(
+                client,
+                board(&ServerMessage::keyframe(&world)),
+                "tick {}",
+                i
+            );
+        }
+        assert!(deaths > 0);
+        assert!(spawned_food > 0);
+        // Food eaten with the score multiplier active.
+        assert_eq!(most_points, SCORE_MULTIPLIER);
+    }
+
+    #[test]
+    fn no_delta_before_keyframe() {
+        let delta = Arc::new(ServerMessage::Lobby { starts_in: None });
+        let keyframe = Arc::new(ServerMessage::Lobby { starts_in: Some(1) });
+        let (mut client, _rx) = Client::new(true);
+
+        // Added after the tick decided no keyframe was needed.
+        assert!(client.update(&delta, None, false).is_none());
+        let update = client.update(&delta, Some(&keyframe), true).unwrap();
+        assert!(Arc::ptr_eq(&update, &keyframe));
+        let update = client.update(&delta, None, false).unwrap();
+        assert!(Arc::ptr_eq(&update, &delta));
+    }
+
+    fn room() -> Room {
+        let settings = RoomSettings::from(&Args::parse_from(["backend", "--resume-grace", "5"]));
+        Room::new(
+            "test".to_string(),
+            settings,
+            None,
+            Arc::new(Leaderboard::default()),
+        )
+    }
+
+    #[test]
+    fn resume_within_grace_period() {
+        let room = room();
+        let handle = room.spawn_player("a".to_string(), 16, None, false);
+        room.disconnect_player(&handle.uuid, &handle.connection);
+        room.remove_expired_players();
+
+        assert!(room.has_session(&handle.token));
+        let resumed = room.resume_player(&handle.token, false).unwrap();
+        assert_eq!(resumed.uuid, handle.uuid);
+        assert_ne!(resumed.connection, handle.connection);
+        assert!(room.detached.is_empty());
+
+        // Old connection closing late leaves the resumed one attached.
+        room.disconnect_player(&handle.uuid, &handle.connection);
+        assert!(room.push_input(handle.uuid, Direction::Up));
+    }
+
+    #[test]
+    fn session_expires_after_grace_period() {
+        let room = room();
+        let handle = room.spawn_player("a".to_string(), 16, None, false);
+        room.disconnect_player(&handle.uuid, &handle.connection);
+        room.detached
+            .insert(handle.uuid, Instant::now() - Duration::from_secs(5));
+        room.remove_expired_players();
+
+        assert!(!room.has_session(&handle.token));
+        assert!(room.resume_player(&handle.token, false).is_none());
+        assert!(!room.world.lock().players().contains_key(&handle.uuid));
+    }
+
+    #[test]
+    fn settings_must_fit_field() {
+        let settings = RoomSettings::from(&Args::parse_from([
+            "backend", "-w", "5", "-h", "5", "-c", "10", "-f", "10",
+        ]));
+        assert!(settings.validate().is_ok());
+
+        let power_ups = RoomSettings {
+            power_up_count: 6,
+            ..settings.clone()
+        };
+        assert!(power_ups.validate().is_err());
+        let bots = RoomSettings {
+            bots: 6,
+            ..settings
+        };
+        assert!(bots.validate().is_err());
+    }
+
+    #[test]
+    fn chat_text_is_cleaned() {
+        assert_eq!(
+            clean_chat_text("  hi\u{7}\n there ", 100).unwrap(),
+            "hi there"
+        );
+        assert_eq!(clean_chat_text("żółw", 2).unwrap(), "żó");
+        assert_eq!(clean_chat_text(" \t\r\n", 100), None);
+    }
+}
@@ -19,8 +19,10 @@ impl Snake {
         }
     }
 
-    pub fn do_move(&mut self) -> (Point, Point) {
-        let new_head = *self.parts.front().unwrap() + self.direction;
+    /// Moves the head one step, `wrap` maps the new head back onto the field
+    /// when it wraps around an edge.
+    pub fn do_move(&mut self, wrap: impl Fn(Point) -> Point) -> (Point, Point) {
+        let new_head = wrap(*self.parts.front().unwrap() + self.direction);
         let last = *self.parts.back().unwrap();
         self.parts.push_front(new_head);
         (new_head, last)
@@ -32,13 +34,13 @@ impl Snake {
         self.direction = direction;
     }
 
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
     pub fn set_direction(&mut self, direction: Direction) {
-        match (self.direction, direction) {
-            (Direction::Up, Direction::Down) => (),
-            (Direction::Right, Direction::Left) => (),
-            (Direction::Down, Direction::Up) => (),
-            (Direction::Left, Direction::Right) => (),
-            _ => self.direction = direction,
+        if direction != self.direction.opposite() {
+            self.direction = direction;
         }
     }
 
@@ -1,22 +1,32 @@
-use std::{collections::VecDeque, ops::Add, sync::atomic::AtomicBool};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    ops::Add,
+};
 
-use dashmap::{DashMap, DashSet};
+use clap::ValueEnum;
 use rand::{distributions::Standard, prelude::Distribution, Rng};
 use serde::{Deserialize, Serialize};
-use tokio::sync::mpsc::Sender;
 use uuid::Uuid;
 
-use super::snake::Snake;
+use super::{bot::Difficulty, power_up::PowerUp, snake::Snake, world::Tick};
 
 pub type Score = usize;
 pub type Name = String;
 pub type FieldWidthT = isize;
 pub type FieldHeightT = isize;
+pub type RoomId = String;
+pub type ResumeToken = Uuid;
+pub type TeamId = u8;
 
-pub type PlayerInfo = (Snake, Uuid, Name, Score);
+/// Snake, uuid, name, score, whether the player is a bot and its team.
+pub type PlayerInfo = (Snake, Uuid, Name, Score, bool, Option<TeamId>);
+/// Player's place at the end of a round: uuid, name and score.
+pub type Standing = (Uuid, Name, Score);
+/// Snake uuid with its new head and the tail part removed in this tick.
+pub type SnakeMove = (Uuid, Point, Option<Point>);
 
 #[repr(u8)]
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Direction {
     Up = 0,
     Right = 1,
@@ -24,6 +34,24 @@ pub enum Direction {
     Left = 3,
 }
 
+impl Direction {
+    pub const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Right,
+        Direction::Down,
+        Direction::Left,
+    ];
+
+    pub fn opposite(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Right => Direction::Left,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+        }
+    }
+}
+
 impl Add<Direction> for Point {
     type Output = Point;
 
@@ -40,7 +68,28 @@ impl Add<Direction> for Point {
     }
 }
 
-#[derive(Debug, Hash, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+/// Field edges snakes pass through, coming out on the opposite side,
+/// instead of dying.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Wrap {
+    #[default]
+    None,
+    X,
+    Y,
+    Both,
+}
+
+impl Wrap {
+    pub fn wraps_x(self) -> bool {
+        matches!(self, Wrap::X | Wrap::Both)
+    }
+
+    pub fn wraps_y(self) -> bool {
+        matches!(self, Wrap::Y | Wrap::Both)
+    }
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Serialize, Deserialize)]
 pub struct Point {
     pub x: FieldWidthT,
     pub y: FieldWidthT,
@@ -53,6 +102,50 @@ pub struct Colour {
     pub b: u8,
 }
 
+/// Colours of team snakes, also limiting the number of teams.
+pub const TEAM_COLOURS: [Colour; 8] = [
+    Colour {
+        r: 220,
+        g: 50,
+        b: 47,
+    },
+    Colour {
+        r: 38,
+        g: 139,
+        b: 210,
+    },
+    Colour {
+        r: 133,
+        g: 153,
+        b: 0,
+    },
+    Colour {
+        r: 181,
+        g: 137,
+        b: 0,
+    },
+    Colour {
+        r: 211,
+        g: 54,
+        b: 130,
+    },
+    Colour {
+        r: 42,
+        g: 161,
+        b: 152,
+    },
+    Colour {
+        r: 203,
+        g: 75,
+        b: 22,
+    },
+    Colour {
+        r: 108,
+        g: 113,
+        b: 196,
+    },
+];
+
 impl Distribution<Direction> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Direction {
         let rand_direction = rng.gen_range(0..3);
@@ -86,37 +179,77 @@ impl Distribution<Colour> for Standard {
 pub struct PlayerData {
     pub name: String,
     pub snake: Snake,
-    pub last_move: Option<Direction>,
-    pub tx: Sender<()>,
+    /// Turns waiting to be applied, one per tick
+    pub input_queue: VecDeque<Direction>,
     pub score: Score,
+    /// Set for server controlled players
+    pub bot: Option<Difficulty>,
+    pub team: Option<TeamId>,
+    /// False while waiting for the next round after being killed
+    pub alive: bool,
+    /// Active power-ups with the tick they wear off at
+    pub effects: BTreeMap<PowerUp, Tick>,
+    /// Sub-ticks between moves without power-ups
+    pub move_interval: u32,
+    /// Half sub-ticks left until the next move
+    move_cooldown: u32,
 }
 
 impl PlayerData {
-    pub fn new(
-        name: String,
-        starting_point: Point,
-        colour: Colour,
-        direction: Direction,
-        tx: Sender<()>,
-    ) -> Self {
+    pub fn new(name: String, starting_point: Point, colour: Colour, direction: Direction) -> Self {
         PlayerData {
             name,
             snake: Snake::new(VecDeque::from([starting_point]), colour, direction),
-            last_move: None,
-            tx,
+            input_queue: VecDeque::new(),
             score: 0,
+            bot: None,
+            team: None,
+            alive: true,
+            effects: BTreeMap::new(),
+            move_interval: 1,
+            move_cooldown: 0,
+        }
+    }
+
+    pub fn has_effect(&self, power_up: PowerUp, tick: Tick) -> bool {
+        self.effects
+            .get(&power_up)
+            .is_some_and(|until| *until > tick)
+    }
+
+    /// Advances the snake's movement clock by half a sub-tick, twice as fast
+    /// when `boosted`, returns whether it moves in this half. Counting halves
+    /// lets a speed boost double any speed, even with a single sub-tick.
+    pub fn half_sub_tick(&mut self, boosted: bool) -> bool {
+        let moves = self.move_cooldown == 0;
+        if moves {
+            self.move_cooldown = self.move_interval * 2;
+        }
+        let elapsed = if boosted { 2 } else { 1 };
+        self.move_cooldown = self.move_cooldown.saturating_sub(elapsed);
+        moves
+    }
+
+    /// Queues a turn unless it is a no-op or a U-turn relative to the
+    /// direction queued before it. Turns over `max_len` are dropped.
+    pub fn queue_turn(&mut self, direction: Direction, max_len: usize) {
+        let previous = self
+            .input_queue
+            .back()
+            .copied()
+            .unwrap_or_else(|| self.snake.direction());
+        if direction == previous || direction == previous.opposite() {
+            return;
+        }
+        if self.input_queue.len() < max_len {
+            self.input_queue.push_back(direction);
         }
     }
 
     pub fn killed_restart(&mut self, starting_point: Point, direction: Direction) {
         self.snake.killed_restart(starting_point, direction);
-        self.last_move = None;
+        self.input_queue.clear();
+        self.effects.clear();
+        self.move_cooldown = 0;
     }
 }
-
-pub struct State {
-    pub players: DashMap<Uuid, PlayerData>,
-    pub map_state: DashMap<Point, Uuid>,
-    pub is_running: AtomicBool,
-    pub food: DashSet<Point>,
-}
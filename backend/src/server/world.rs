@@ -0,0 +1,1170 @@
+use rand::prelude::*;
+use rand_chacha::ChaCha20Rng;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use uuid::Uuid;
+
+use super::bot::{self, Difficulty};
+use super::power_up::{PowerUp, PowerUpWeights, EFFECT_TICKS, SCORE_MULTIPLIER};
+use super::types::{
+    Colour, Direction, FieldHeightT, FieldWidthT, Name, PlayerData, PlayerInfo, Point, Score,
+    SnakeMove, Standing, TeamId, Wrap, TEAM_COLOURS,
+};
+
+pub type Seed = u64;
+pub type Tick = u64;
+
+/// Random cells tried before looking through the whole field.
+const RANDOM_POINT_TRIES: usize = 64;
+
+/// Settings the simulation depends on.
+#[derive(Debug, Clone)]
+pub struct WorldSettings {
+    pub field_width: FieldWidthT,
+    pub field_height: FieldHeightT,
+    pub food_count: usize,
+    pub input_queue_len: usize,
+    /// Steps a tick is split into, snakes move on some of them depending on
+    /// their speed
+    pub sub_ticks: u32,
+    /// Sub-ticks between a bot's moves, 0 for one move per tick like players
+    pub bot_move_interval: u32,
+    pub wrap: Wrap,
+    pub walls: Vec<Point>,
+    /// Cells snakes spawn on, any free cell when empty
+    pub spawn_points: Vec<Point>,
+    /// Cells food appears on, any free cell when empty
+    pub food_zones: Vec<Point>,
+    /// Power-ups kept on the field
+    pub power_up_count: usize,
+    pub power_up_weights: PowerUpWeights,
+    /// Number of teams, 0 for everyone on their own
+    pub teams: TeamId,
+    /// Whether running into a teammate's body kills
+    pub friendly_fire: bool,
+    /// Whether killed snakes come back at once, otherwise they wait for the
+    /// next round
+    pub respawn: bool,
+}
+
+impl WorldSettings {
+    /// Cells not taken by walls.
+    pub fn free_cells(&self) -> usize {
+        self.field_width as usize * self.field_height as usize - self.walls.len()
+    }
+
+    /// Checks the field has room for the food, the power-ups and `players`
+    /// snakes, so every snake can spawn.
+    pub fn validate(&self, players: usize) -> Result<(), String> {
+        if self.field_width < 1 || self.field_height < 1 {
+            return Err(format!(
+                "Field must be at least 1x1, got {}x{}",
+                self.field_width, self.field_height
+            ));
+        }
+        let needed = self.food_count + self.power_up_count + players;
+        if needed > self.free_cells() {
+            return Err(format!(
+                "Food count {}, {} power-ups and {} snakes do not fit in the {} free cells",
+                self.food_count,
+                self.power_up_count,
+                players,
+                self.free_cells()
+            ));
+        }
+        if self.teams as usize > TEAM_COLOURS.len() {
+            return Err(format!(
+                "At most {} teams are supported",
+                TEAM_COLOURS.len()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Brings a point that left the field through a wrapping edge back
+    /// onto the field.
+    pub fn wrap_point(&self, mut point: Point) -> Point {
+        if self.wrap.wraps_x() {
+            point.x = point.x.rem_euclid(self.field_width);
+        }
+        if self.wrap.wraps_y() {
+            point.y = point.y.rem_euclid(self.field_height);
+        }
+        point
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeathCause {
+    /// Left the field
+    Wall,
+    /// Ran into its own body
+    Itself,
+    /// Ran into another snake's body
+    Snake { killer: Uuid },
+    /// Two or more heads moved into the same cell
+    HeadOn,
+}
+
+#[derive(Debug, Clone)]
+pub struct Death {
+    pub uuid: Uuid,
+    pub cause: DeathCause,
+    /// Score and length the snake had when it died
+    pub score: Score,
+    pub length: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cell {
+    Snake(Uuid),
+    Wall,
+}
+
+/// What happened during a single `World::step`.
+#[derive(Debug, Default)]
+pub struct TickEvents {
+    pub tick: Tick,
+    pub moves: Vec<SnakeMove>,
+    /// Snakes that ate, the food and their new score
+    pub eaten: Vec<(Uuid, Point, Score)>,
+    pub deaths: Vec<Death>,
+    pub respawned: Vec<(Uuid, Point)>,
+    pub spawned_food: Vec<Point>,
+    pub picked_up: Vec<(Uuid, Point)>,
+    /// Snakes shortened by `PowerUp::Shrink` and how many tail parts they lost
+    pub shrunk: Vec<(Uuid, usize)>,
+    pub spawned_power_ups: Vec<(Point, PowerUp)>,
+}
+
+/// Game rules without any networking, driven one tick at a time.
+///
+/// Given the same seed, settings and sequence of calls the world always ends
+/// up in the same state.
+pub struct World {
+    settings: WorldSettings,
+    rng: ChaCha20Rng,
+    tick: Tick,
+    players: BTreeMap<Uuid, PlayerData>,
+    map_state: HashMap<Point, Cell>,
+    food: BTreeSet<Point>,
+    power_ups: BTreeMap<Point, PowerUp>,
+    /// Players killed in the current round, in order
+    eliminated: Vec<Uuid>,
+}
+
+impl World {
+    pub fn new(settings: WorldSettings, seed: Seed) -> Self {
+        let mut world = World {
+            map_state: HashMap::with_capacity(
+                settings.field_height as usize * settings.field_width as usize,
+            ),
+            settings,
+            rng: ChaCha20Rng::seed_from_u64(seed),
+            tick: 0,
+            players: BTreeMap::new(),
+            food: BTreeSet::new(),
+            power_ups: BTreeMap::new(),
+            eliminated: Vec::new(),
+        };
+        for wall in &world.settings.walls {
+            world.map_state.insert(*wall, Cell::Wall);
+        }
+        world.refill_food();
+        world.refill_power_ups();
+        world
+    }
+
+    pub fn settings(&self) -> &WorldSettings {
+        &self.settings
+    }
+
+    pub fn tick(&self) -> Tick {
+        self.tick
+    }
+
+    pub fn players(&self) -> &BTreeMap<Uuid, PlayerData> {
+        &self.players
+    }
+
+    pub fn food(&self) -> impl Iterator<Item = &Point> {
+        self.food.iter()
+    }
+
+    pub fn has_food(&self, point: &Point) -> bool {
+        self.food.contains(point)
+    }
+
+    pub fn power_ups(&self) -> impl Iterator<Item = (&Point, &PowerUp)> {
+        self.power_ups.iter()
+    }
+
+    pub fn human_count(&self) -> usize {
+        self.players
+            .values()
+            .filter(|player| player.bot.is_none())
+            .count()
+    }
+
+    /// Players with a snake on the field.
+    pub fn alive_count(&self) -> usize {
+        self.players.values().filter(|player| player.alive).count()
+    }
+
+    /// Cell reached by moving one step from `point`.
+    pub fn neighbour(&self, point: &Point, direction: Direction) -> Point {
+        self.settings.wrap_point(*point + direction)
+    }
+
+    /// Whether a snake can move into the cell without dying.
+    pub fn is_free(&self, point: &Point) -> bool {
+        self.is_in_map(point) && !self.map_state.contains_key(point)
+    }
+
+    pub fn player_infos(&self) -> Vec<PlayerInfo> {
+        self.players
+            .iter()
+            .filter(|(_, player)| player.alive)
+            .map(|(uuid, player)| {
+                (
+                    player.snake.clone(),
+                    *uuid,
+                    player.name.clone(),
+                    player.score,
+                    player.bot.is_some(),
+                    player.team,
+                )
+            })
+            .collect()
+    }
+
+    pub fn spawn_player(&mut self, uuid: Uuid, name: Name) -> Option<Point> {
+        self.spawn(uuid, name, None, None)
+    }
+
+    /// Spawns a player steered by the world itself.
+    pub fn spawn_bot(&mut self, uuid: Uuid, name: Name, difficulty: Difficulty) -> Option<Point> {
+        self.spawn(uuid, name, Some(difficulty), None)
+    }
+
+    /// Total score of every team, indexed by team id.
+    pub fn team_scores(&self) -> Vec<Score> {
+        let mut scores = vec![0; self.settings.teams as usize];
+        for player in self.players.values() {
+            if let Some(team) = player.team {
+                scores[team as usize] += player.score;
+            }
+        }
+        scores
+    }
+
+    /// Whether `a` runs through `b`'s body without dying.
+    fn is_friendly(&self, a: &Uuid, b: &Uuid) -> bool {
+        !self.settings.friendly_fire
+            && self.players[a].team.is_some()
+            && self.players[a].team == self.players.get(b).and_then(|player| player.team)
+    }
+
+    /// The requested team if it exists, otherwise the one with fewest players.
+    fn assign_team(&self, requested: Option<TeamId>) -> Option<TeamId> {
+        let teams = self.settings.teams;
+        if teams == 0 {
+            return None;
+        }
+        if let Some(team) = requested.filter(|team| *team < teams) {
+            return Some(team);
+        }
+        (0..teams).min_by_key(|team| {
+            self.players
+                .values()
+                .filter(|player| player.team == Some(*team))
+                .count()
+        })
+    }
+
+    /// Food kept on the field from the next tick on. Surplus food is not
+    /// removed, it is just not replaced once eaten.
+    pub fn set_food_count(&mut self, food_count: usize) {
+        self.settings.food_count = food_count;
+    }
+
+    /// Adds a player, in team mode to the requested team or the smallest one.
+    /// Returns `None` if the field is full, the snake then waits for a free
+    /// cell like a killed one.
+    pub fn spawn(
+        &mut self,
+        uuid: Uuid,
+        name: Name,
+        bot: Option<Difficulty>,
+        team: Option<TeamId>,
+    ) -> Option<Point> {
+        assert!(!self.players.contains_key(&uuid));
+        let team = self.assign_team(team);
+        let colour: Colour = match team {
+            Some(team) => TEAM_COLOURS[team as usize % TEAM_COLOURS.len()].clone(),
+            None => self.rng.gen(),
+        };
+        let direction: Direction = self.rng.gen();
+        let starting_point = self.random_spawn_point();
+        let mut player = PlayerData::new(
+            name,
+            starting_point.unwrap_or(Point { x: 0, y: 0 }),
+            colour,
+            direction,
+        );
+        player.bot = bot;
+        player.team = team;
+        player.move_interval = match bot {
+            Some(_) if self.settings.bot_move_interval > 0 => self.settings.bot_move_interval,
+            _ => self.settings.sub_ticks.max(1),
+        };
+        // Without respawns players joining wait for the next round.
+        player.alive = self.settings.respawn && starting_point.is_some();
+        if let Some(starting_point) = starting_point.filter(|_| player.alive) {
+            self.map_state.insert(starting_point, Cell::Snake(uuid));
+        }
+        self.players.insert(uuid, player);
+
+        starting_point
+    }
+
+    pub fn remove_player(&mut self, uuid: &Uuid) -> Option<PlayerData> {
+        self.clear_player_parts(uuid);
+        self.eliminated.retain(|player| player != uuid);
+        self.players.remove(uuid)
+    }
+
+    /// Puts every player back on the field with a fresh snake and no score.
+    pub fn start_round(&mut self) {
+        let uuids: Vec<Uuid> = self.players.keys().copied().collect();
+        for uuid in &uuids {
+            self.clear_player_parts(uuid);
+        }
+        self.eliminated.clear();
+        for uuid in uuids {
+            // Players not fitting on the field sit the round out.
+            let starting_point = match self.random_spawn_point() {
+                Some(point) => point,
+                None => continue,
+            };
+            let direction: Direction = self.rng.gen();
+            if let Some(player) = self.players.get_mut(&uuid) {
+                player.killed_restart(starting_point, direction);
+                player.score = 0;
+                player.alive = true;
+            }
+            self.map_state.insert(starting_point, Cell::Snake(uuid));
+        }
+    }
+
+    /// Takes the remaining snakes off the field and ranks the players:
+    /// survivors by score, then the killed ones, last killed first.
+    pub fn end_round(&mut self) -> Vec<Standing> {
+        let mut survivors: Vec<Uuid> = self
+            .players
+            .iter()
+            .filter(|(_, player)| player.alive)
+            .map(|(uuid, _)| *uuid)
+            .collect();
+        survivors.sort_by_key(|uuid| std::cmp::Reverse(self.players[uuid].score));
+        for uuid in &survivors {
+            self.clear_player_parts(uuid);
+            if let Some(player) = self.players.get_mut(uuid) {
+                player.alive = false;
+            }
+        }
+
+        let eliminated = std::mem::take(&mut self.eliminated);
+        survivors
+            .iter()
+            .chain(eliminated.iter().rev())
+            .filter_map(|uuid| {
+                let player = self.players.get(uuid)?;
+                Some((*uuid, player.name.clone(), player.score))
+            })
+            .collect()
+    }
+
+    /// Advances the game by one tick. Inputs are queued per player in order
+    /// and each snake applies at most one turn per move.
+    pub fn step(&mut self, inputs: &[(Uuid, Direction)]) -> TickEvents {
+        self.tick += 1;
+        let mut events = TickEvents {
+            tick: self.tick,
+            ..Default::default()
+        };
+
+        let bots: Vec<(Uuid, Difficulty)> = self
+            .players
+            .iter()
+            .filter(|(_, player)| player.alive)
+            .filter_map(|(uuid, player)| player.bot.map(|difficulty| (*uuid, difficulty)))
+            .collect();
+        let mut bot_inputs = Vec::with_capacity(bots.len());
+        for (uuid, difficulty) in bots {
+            let roll: f64 = self.rng.gen();
+            if let Some(direction) = bot::choose_direction(self, &uuid, difficulty, roll) {
+                bot_inputs.push((uuid, direction));
+            }
+        }
+
+        for (uuid, direction) in inputs.iter().chain(bot_inputs.iter()) {
+            if let Some(player) = self.players.get_mut(uuid) {
+                player.queue_turn(*direction, self.settings.input_queue_len);
+            }
+        }
+
+        // Killed snakes sit out the rest of the tick and respawn at its end,
+        // so every move in `events` happens before any respawn.
+        // Speed boosts picked up during the tick apply from the next one.
+        let mut killed = Vec::new();
+        let boosted: Vec<Uuid> = self
+            .players
+            .iter()
+            .filter(|(_, player)| player.has_effect(PowerUp::SpeedBoost, self.tick))
+            .map(|(uuid, _)| *uuid)
+            .collect();
+        for _ in 0..self.settings.sub_ticks.max(1) * 2 {
+            let movers: Vec<Uuid> = self
+                .players
+                .iter_mut()
+                .filter(|(uuid, _)| !killed.contains(*uuid))
+                .filter_map(|(uuid, player)| {
+                    (player.alive && player.half_sub_tick(boosted.contains(uuid))).then_some(*uuid)
+                })
+                .collect();
+            self.move_snakes(&movers, &mut killed, &mut events);
+        }
+
+        // Players waiting for a free cell since an earlier tick respawn after
+        // the ones killed now.
+        let waiting: Vec<Uuid> = self
+            .players
+            .iter()
+            .filter(|(uuid, player)| {
+                self.settings.respawn && !player.alive && !killed.contains(*uuid)
+            })
+            .map(|(uuid, _)| *uuid)
+            .collect();
+        for killed_player in killed {
+            if !self.settings.respawn {
+                if let Some(player_data) = self.players.get_mut(&killed_player) {
+                    player_data.alive = false;
+                }
+                self.eliminated.push(killed_player);
+                continue;
+            }
+            self.respawn(killed_player, &mut events);
+        }
+        for uuid in waiting {
+            self.respawn(uuid, &mut events);
+        }
+
+        events.spawned_food = self.refill_food();
+        events.spawned_power_ups = self.refill_power_ups();
+
+        events
+    }
+
+    /// Puts the player back on the field with a fresh snake and no score, or
+    /// keeps it waiting if the field is full.
+    fn respawn(&mut self, uuid: Uuid, events: &mut TickEvents) {
+        let starting_point = match self.random_spawn_point() {
+            Some(point) => point,
+            None => {
+                if let Some(player_data) = self.players.get_mut(&uuid) {
+                    player_data.alive = false;
+                }
+                return;
+            }
+        };
+        let direction: Direction = self.rng.gen();
+        if let Some(player_data) = self.players.get_mut(&uuid) {
+            player_data.killed_restart(starting_point, direction);
+            player_data.score = 0;
+            player_data.alive = true;
+        }
+        self.map_state.insert(starting_point, Cell::Snake(uuid));
+        events.respawned.push((uuid, starting_point));
+    }
+
+    /// Moves the given snakes one cell and resolves their collisions. Snakes
+    /// not listed stay where they are but can still be run into. Killed
+    /// snakes are cleared from the field and added to `killed`.
+    fn move_snakes(&mut self, movers: &[Uuid], killed: &mut Vec<Uuid>, events: &mut TickEvents) {
+        let tick = self.tick;
+        let mut new_heads = BTreeMap::<Point, Vec<Uuid>>::new();
+        for uuid in movers {
+            let player = match self.players.get_mut(uuid) {
+                Some(player) => player,
+                None => continue,
+            };
+            if let Some(direction) = player.input_queue.pop_front() {
+                player.snake.set_direction(direction)
+            }
+            let (new_head, last) = player.snake.do_move(|head| self.settings.wrap_point(head));
+            new_heads.entry(new_head).or_default().push(*uuid);
+            if self.food.remove(&new_head) {
+                player.score += if player.has_effect(PowerUp::ScoreMultiplier, tick) {
+                    SCORE_MULTIPLIER
+                } else {
+                    1
+                };
+                events.eaten.push((*uuid, new_head, player.score));
+                events.moves.push((*uuid, new_head, None));
+            } else {
+                if self.map_state.get(&last) == Some(&Cell::Snake(*uuid)) {
+                    self.map_state.remove(&last);
+                }
+                player.snake.pop_last();
+                events.moves.push((*uuid, new_head, Some(last)));
+            }
+
+            if let Some(power_up) = self.power_ups.remove(&new_head) {
+                events.picked_up.push((*uuid, new_head));
+                if power_up == PowerUp::Shrink {
+                    let cut = player.snake.parts.len() / 2;
+                    for _ in 0..cut {
+                        if let Some(part) = player.snake.parts.pop_back() {
+                            if self.map_state.get(&part) == Some(&Cell::Snake(*uuid)) {
+                                self.map_state.remove(&part);
+                            }
+                        }
+                    }
+                    if cut > 0 {
+                        events.shrunk.push((*uuid, cut));
+                    }
+                } else {
+                    player.effects.insert(power_up, tick + EFFECT_TICKS);
+                }
+            }
+        }
+
+        let mut deaths = Vec::new();
+        for (new_head, uuids) in new_heads {
+            let (ghosts, solid): (Vec<Uuid>, Vec<Uuid>) = uuids
+                .into_iter()
+                .partition(|uuid| self.players[uuid].has_effect(PowerUp::Ghost, tick));
+            let hits_wall =
+                !self.is_in_map(&new_head) || self.map_state.get(&new_head) == Some(&Cell::Wall);
+            if hits_wall {
+                deaths.extend(
+                    ghosts
+                        .into_iter()
+                        .chain(solid)
+                        .map(|uuid| (uuid, DeathCause::Wall)),
+                );
+                continue;
+            }
+
+            let cause = if solid.len() > 1 {
+                Some(DeathCause::HeadOn)
+            } else {
+                match (solid.first(), self.map_state.get(&new_head)) {
+                    (Some(uuid), Some(Cell::Snake(owner))) if owner == uuid => {
+                        Some(DeathCause::Itself)
+                    }
+                    (Some(uuid), Some(Cell::Snake(owner))) if self.is_friendly(uuid, owner) => None,
+                    (Some(_), Some(Cell::Snake(owner))) => {
+                        Some(DeathCause::Snake { killer: *owner })
+                    }
+                    _ => None,
+                }
+            };
+            match cause {
+                Some(cause) => deaths.extend(solid.into_iter().map(|uuid| (uuid, cause))),
+                None => {
+                    // Ghosts and teammates passing through only take cells
+                    // nobody else is in.
+                    if let Some(uuid) = solid.first().or_else(|| ghosts.first()) {
+                        self.map_state.entry(new_head).or_insert(Cell::Snake(*uuid));
+                    }
+                }
+            }
+        }
+
+        for (killed_player, cause) in deaths {
+            self.clear_player_parts(&killed_player);
+            if let Some(player_data) = self.players.get(&killed_player) {
+                events.deaths.push(Death {
+                    uuid: killed_player,
+                    cause,
+                    score: player_data.score,
+                    length: player_data.snake.parts.len(),
+                });
+            }
+            killed.push(killed_player);
+        }
+    }
+
+    /// Random empty cell, `None` when the field is full.
+    fn random_free_point(&mut self) -> Option<Point> {
+        // Guessing is quick while the field is mostly empty.
+        for _ in 0..RANDOM_POINT_TRIES {
+            let point = Point {
+                x: self.rng.gen_range(0..self.settings.field_width),
+                y: self.rng.gen_range(0..self.settings.field_height),
+            };
+            if self.is_empty_cell(&point) {
+                return Some(point);
+            }
+        }
+        let (field_width, field_height) = (self.settings.field_width, self.settings.field_height);
+        let cells: Vec<Point> = (0..field_height)
+            .flat_map(|y| (0..field_width).map(move |x| Point { x, y }))
+            .collect();
+        self.random_free_point_of(&cells)
+    }
+
+    /// Free spawn point if the map has any, otherwise any free cell. `None`
+    /// when the field is full.
+    fn random_spawn_point(&mut self) -> Option<Point> {
+        self.random_free_point_of(&self.settings.spawn_points.clone())
+            .or_else(|| self.random_free_point())
+    }
+
+    /// Free cell in the food zones if the map has any, otherwise any free
+    /// cell. `None` when all food zones are taken.
+    fn random_food_point(&mut self) -> Option<Point> {
+        if self.settings.food_zones.is_empty() {
+            self.random_free_point()
+        } else {
+            self.random_free_point_of(&self.settings.food_zones.clone())
+        }
+    }
+
+    fn random_free_point_of(&mut self, points: &[Point]) -> Option<Point> {
+        let free: Vec<&Point> = points
+            .iter()
+            .filter(|point| self.is_empty_cell(point))
+            .collect();
+        if free.is_empty() {
+            None
+        } else {
+            Some(*free[self.rng.gen_range(0..free.len())])
+        }
+    }
+
+    /// Whether nothing, not even an item, is in the cell.
+    fn is_empty_cell(&self, point: &Point) -> bool {
+        !self.map_state.contains_key(point)
+            && !self.food.contains(point)
+            && !self.power_ups.contains_key(point)
+    }
+
+    fn is_in_map(&self, point: &Point) -> bool {
+        let field_width = self.settings.field_width;
+        let field_height = self.settings.field_height;
+        match point {
+            Point { x, y } if *x < 0 || *y < 0 => false,
+            Point { x, y: _ } if *x >= field_width => false,
+            Point { x: _, y } if *y >= field_height => false,
+            _ => true,
+        }
+    }
+
+    fn refill_food(&mut self) -> Vec<Point> {
+        let curr_food_count = self.food.len();
+        let mut spawned = Vec::new();
+        for _ in curr_food_count..self.settings.food_count {
+            match self.random_food_point() {
+                Some(food) => {
+                    self.food.insert(food);
+                    spawned.push(food);
+                }
+                None => break,
+            }
+        }
+        spawned
+    }
+
+    fn refill_power_ups(&mut self) -> Vec<(Point, PowerUp)> {
+        let mut spawned = Vec::new();
+        while self.power_ups.len() < self.settings.power_up_count {
+            let power_up = match self.settings.power_up_weights.choose(&mut self.rng) {
+                Some(power_up) => power_up,
+                None => break,
+            };
+            match self.random_food_point() {
+                Some(point) => {
+                    self.power_ups.insert(point, power_up);
+                    spawned.push((point, power_up));
+                }
+                None => break,
+            }
+        }
+        spawned
+    }
+
+    fn clear_player_parts(&mut self, uuid: &Uuid) {
+        if let Some(player) = self.players.get(uuid) {
+            for p in &player.snake.parts {
+                // The head of a killed snake may lie on a wall or other snake.
+                if self.map_state.get(p) == Some(&Cell::Snake(*uuid)) {
+                    self.map_state.remove(p);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> WorldSettings {
+        WorldSettings {
+            field_width: 10,
+            field_height: 8,
+            food_count: 3,
+            input_queue_len: 3,
+            sub_ticks: 1,
+            bot_move_interval: 0,
+            wrap: Wrap::None,
+            walls: Vec::new(),
+            spawn_points: Vec::new(),
+            food_zones: Vec::new(),
+            power_up_count: 0,
+            power_up_weights: PowerUpWeights::default(),
+            teams: 0,
+            friendly_fire: true,
+            respawn: true,
+        }
+    }
+
+    fn place(world: &mut World, uuid: Uuid, head: Point, direction: Direction) {
+        world.clear_player_parts(&uuid);
+        let player = world.players.get_mut(&uuid).unwrap();
+        player.killed_restart(head, direction);
+        world.map_state.insert(head, Cell::Snake(uuid));
+    }
+
+    /// Appends `parts` to the tail of the snake.
+    fn grow(world: &mut World, uuid: Uuid, parts: &[Point]) {
+        for part in parts {
+            let player = world.players.get_mut(&uuid).unwrap();
+            player.snake.parts.push_back(*part);
+            world.map_state.insert(*part, Cell::Snake(uuid));
+        }
+    }
+
+    #[test]
+    fn same_seed_same_game() {
+        let run = |seed| {
+            let mut world = World::new(settings(), seed);
+            let a = Uuid::from_u128(1);
+            let b = Uuid::from_u128(2);
+            world.spawn_player(a, "a".into());
+            world.spawn_player(b, "b".into());
+            for i in 0..50 {
+                let direction = if i % 3 == 0 {
+                    Direction::Left
+                } else {
+                    Direction::Down
+                };
+                world.step(&[(a, direction), (b, Direction::Up)]);
+            }
+            let mut food: Vec<Point> = world.food().copied().collect();
+            food.sort_by_key(|p| (p.x, p.y));
+            let snakes: Vec<_> = world
+                .players()
+                .values()
+                .map(|p| (p.snake.parts.clone(), p.score))
+                .collect();
+            (food, snakes)
+        };
+
+        assert_eq!(format!("{:?}", run(7)), format!("{:?}", run(7)));
+    }
+
+    #[test]
+    fn eating_grows_snake() {
+        let mut world = World::new(settings(), 1);
+        let uuid = Uuid::from_u128(1);
+        world.spawn_player(uuid, "a".into());
+        world.food.clear();
+        place(&mut world, uuid, Point { x: 2, y: 2 }, Direction::Right);
+        world.food.insert(Point { x: 3, y: 2 });
+
+        let events = world.step(&[]);
+
+        assert_eq!(events.eaten, vec![(uuid, Point { x: 3, y: 2 }, 1)]);
+        assert_eq!(world.players[&uuid].score, 1);
+        assert_eq!(world.players[&uuid].snake.parts.len(), 2);
+        assert_eq!(world.food.len(), settings().food_count);
+    }
+
+    #[test]
+    fn leaving_field_kills() {
+        let mut world = World::new(settings(), 1);
+        let uuid = Uuid::from_u128(1);
+        world.spawn_player(uuid, "a".into());
+        place(&mut world, uuid, Point { x: 0, y: 0 }, Direction::Up);
+
+        let events = world.step(&[(uuid, Direction::Left)]);
+
+        assert_eq!(events.deaths.len(), 1);
+        assert_eq!(events.deaths[0].uuid, uuid);
+        assert_eq!(events.deaths[0].cause, DeathCause::Wall);
+        assert_eq!(world.players[&uuid].snake.parts.len(), 1);
+    }
+
+    #[test]
+    fn head_on_collision_kills_both() {
+        let mut world = World::new(settings(), 1);
+        let a = Uuid::from_u128(1);
+        let b = Uuid::from_u128(2);
+        world.spawn_player(a, "a".into());
+        world.spawn_player(b, "b".into());
+        world.food.clear();
+        place(&mut world, a, Point { x: 2, y: 2 }, Direction::Right);
+        place(&mut world, b, Point { x: 4, y: 2 }, Direction::Left);
+
+        let events = world.step(&[]);
+
+        assert_eq!(events.deaths.len(), 2);
+        assert!(events
+            .deaths
+            .iter()
+            .all(|death| death.cause == DeathCause::HeadOn));
+    }
+
+    #[test]
+    fn quick_turns_apply_on_consecutive_ticks() {
+        let mut world = World::new(settings(), 1);
+        let uuid = Uuid::from_u128(1);
+        world.spawn_player(uuid, "a".into());
+        place(&mut world, uuid, Point { x: 5, y: 5 }, Direction::Right);
+
+        world.step(&[(uuid, Direction::Up), (uuid, Direction::Left)]);
+        assert_eq!(world.players[&uuid].snake.direction(), Direction::Up);
+        world.step(&[]);
+        assert_eq!(world.players[&uuid].snake.direction(), Direction::Left);
+    }
+
+    #[test]
+    fn u_turn_in_queue_is_dropped() {
+        let mut world = World::new(settings(), 1);
+        let uuid = Uuid::from_u128(1);
+        world.spawn_player(uuid, "a".into());
+        place(&mut world, uuid, Point { x: 5, y: 5 }, Direction::Right);
+
+        world.step(&[
+            (uuid, Direction::Up),
+            (uuid, Direction::Down),
+            (uuid, Direction::Up),
+        ]);
+        assert_eq!(world.players[&uuid].snake.direction(), Direction::Up);
+        assert!(world.players[&uuid].input_queue.is_empty());
+    }
+
+    #[test]
+    fn bot_avoids_wall() {
+        let mut world = World::new(settings(), 1);
+        let uuid = Uuid::from_u128(1);
+        world.spawn_bot(uuid, "bot".into(), Difficulty::Hard);
+        place(&mut world, uuid, Point { x: 0, y: 0 }, Direction::Up);
+
+        let events = world.step(&[]);
+
+        assert!(events.deaths.is_empty());
+    }
+
+    #[test]
+    fn bot_goes_for_food() {
+        let mut world = World::new(settings(), 1);
+        let uuid = Uuid::from_u128(1);
+        world.spawn_bot(uuid, "bot".into(), Difficulty::Hard);
+        world.food.clear();
+        place(&mut world, uuid, Point { x: 2, y: 2 }, Direction::Right);
+        world.food.insert(Point { x: 2, y: 5 });
+
+        for _ in 0..3 {
+            world.step(&[]);
+        }
+
+        assert_eq!(world.players[&uuid].score, 1);
+    }
+
+    #[test]
+    fn running_into_body_credits_owner() {
+        let mut world = World::new(settings(), 1);
+        let a = Uuid::from_u128(1);
+        let b = Uuid::from_u128(2);
+        world.spawn_player(a, "a".into());
+        world.spawn_player(b, "b".into());
+        world.food.clear();
+        place(&mut world, a, Point { x: 2, y: 2 }, Direction::Right);
+        place(&mut world, b, Point { x: 3, y: 3 }, Direction::Down);
+        grow(&mut world, b, &[Point { x: 3, y: 2 }, Point { x: 3, y: 1 }]);
+
+        let events = world.step(&[]);
+
+        assert_eq!(events.deaths.len(), 1);
+        assert_eq!(events.deaths[0].uuid, a);
+        assert_eq!(events.deaths[0].cause, DeathCause::Snake { killer: b });
+    }
+
+    #[test]
+    fn wrapping_edge_moves_to_opposite_side() {
+        let mut world = World::new(
+            WorldSettings {
+                wrap: Wrap::X,
+                ..settings()
+            },
+            1,
+        );
+        let uuid = Uuid::from_u128(1);
+        world.spawn_player(uuid, "a".into());
+        place(&mut world, uuid, Point { x: 0, y: 0 }, Direction::Left);
+
+        let events = world.step(&[]);
+        assert!(events.deaths.is_empty());
+        assert_eq!(world.players[&uuid].snake.parts[0], Point { x: 9, y: 0 });
+
+        let events = world.step(&[(uuid, Direction::Up)]);
+        assert_eq!(events.deaths[0].cause, DeathCause::Wall);
+    }
+
+    #[test]
+    fn full_field_keeps_snake_waiting() {
+        let mut world = World::new(
+            WorldSettings {
+                field_width: 1,
+                field_height: 1,
+                food_count: 0,
+                ..settings()
+            },
+            1,
+        );
+        let a = Uuid::from_u128(1);
+        let b = Uuid::from_u128(2);
+        assert_eq!(
+            world.spawn_player(a, "a".into()),
+            Some(Point { x: 0, y: 0 })
+        );
+        assert_eq!(world.spawn_player(b, "b".into()), None);
+        assert!(!world.players[&b].alive);
+
+        world.remove_player(&a);
+        let events = world.step(&[]);
+
+        assert_eq!(events.respawned, vec![(b, Point { x: 0, y: 0 })]);
+        assert!(world.players[&b].alive);
+    }
+
+    #[test]
+    fn walls_kill_and_block_spawning() {
+        let walls: Vec<Point> = (0..10).map(|x| Point { x, y: 1 }).collect();
+        let mut world = World::new(
+            WorldSettings {
+                walls: walls.clone(),
+                spawn_points: vec![Point { x: 4, y: 4 }],
+                ..settings()
+            },
+            1,
+        );
+        assert!(world.food().all(|food| !walls.contains(food)));
+
+        let uuid = Uuid::from_u128(1);
+        assert_eq!(
+            world.spawn_player(uuid, "a".into()),
+            Some(Point { x: 4, y: 4 })
+        );
+        place(&mut world, uuid, Point { x: 2, y: 2 }, Direction::Up);
+
+        let events = world.step(&[]);
+
+        assert_eq!(events.deaths[0].cause, DeathCause::Wall);
+        assert_eq!(world.map_state[&Point { x: 2, y: 1 }], Cell::Wall);
+    }
+
+    #[test]
+    fn ghost_passes_through_snakes() {
+        let mut world = World::new(settings(), 1);
+        let a = Uuid::from_u128(1);
+        let b = Uuid::from_u128(2);
+        world.spawn_player(a, "a".into());
+        world.spawn_player(b, "b".into());
+        world.food.clear();
+        place(&mut world, a, Point { x: 2, y: 2 }, Direction::Right);
+        place(&mut world, b, Point { x: 3, y: 3 }, Direction::Down);
+        grow(&mut world, b, &[Point { x: 3, y: 2 }, Point { x: 3, y: 1 }]);
+        world
+            .players
+            .get_mut(&a)
+            .unwrap()
+            .effects
+            .insert(PowerUp::Ghost, 10);
+
+        let events = world.step(&[]);
+
+        assert!(events.deaths.is_empty());
+        assert_eq!(world.players[&a].snake.parts[0], Point { x: 3, y: 2 });
+        assert_eq!(world.map_state[&Point { x: 3, y: 2 }], Cell::Snake(b));
+    }
+
+    #[test]
+    fn speed_boost_moves_twice_per_tick() {
+        let mut world = World::new(
+            WorldSettings {
+                sub_ticks: 2,
+                ..settings()
+            },
+            1,
+        );
+        let uuid = Uuid::from_u128(1);
+        world.spawn_player(uuid, "a".into());
+        world.food.clear();
+        place(&mut world, uuid, Point { x: 0, y: 2 }, Direction::Right);
+        world
+            .power_ups
+            .insert(Point { x: 1, y: 2 }, PowerUp::SpeedBoost);
+
+        world.step(&[]);
+        let events = world.step(&[]);
+
+        assert_eq!(events.moves.len(), 2);
+        assert_eq!(world.players[&uuid].snake.parts[0], Point { x: 3, y: 2 });
+    }
+
+    #[test]
+    fn speed_boost_with_single_sub_tick() {
+        let mut world = World::new(settings(), 1);
+        let uuid = Uuid::from_u128(1);
+        world.spawn_player(uuid, "a".into());
+        world.food.clear();
+        place(&mut world, uuid, Point { x: 0, y: 2 }, Direction::Right);
+        world
+            .power_ups
+            .insert(Point { x: 1, y: 2 }, PowerUp::SpeedBoost);
+
+        world.step(&[]);
+        let events = world.step(&[]);
+
+        assert_eq!(events.moves.len(), 2);
+        assert_eq!(world.players[&uuid].snake.parts[0], Point { x: 3, y: 2 });
+    }
+
+    #[test]
+    fn moving_snake_hits_waiting_head() {
+        let mut world = World::new(
+            WorldSettings {
+                sub_ticks: 2,
+                ..settings()
+            },
+            1,
+        );
+        let a = Uuid::from_u128(1);
+        let b = Uuid::from_u128(2);
+        world.spawn_player(a, "a".into());
+        world.spawn_player(b, "b".into());
+        world.food.clear();
+        world.players.get_mut(&a).unwrap().move_interval = 1;
+        world.players.get_mut(&b).unwrap().move_interval = 4;
+        place(&mut world, a, Point { x: 1, y: 2 }, Direction::Right);
+        place(&mut world, b, Point { x: 5, y: 2 }, Direction::Left);
+
+        // b moves once to (4, 2) and waits, a moves twice per tick.
+        let events = world.step(&[]);
+        assert_eq!(events.moves.len(), 3);
+        let events = world.step(&[]);
+
+        assert_eq!(events.deaths.len(), 1);
+        assert_eq!(events.deaths[0].uuid, a);
+        assert_eq!(events.deaths[0].cause, DeathCause::Snake { killer: b });
+        assert_eq!(events.moves.len(), 1);
+        assert_eq!(world.players[&b].snake.parts[0], Point { x: 4, y: 2 });
+    }
+
+    #[test]
+    fn bots_move_at_their_own_speed() {
+        let mut world = World::new(
+            WorldSettings {
+                sub_ticks: 2,
+                bot_move_interval: 4,
+                ..settings()
+            },
+            1,
+        );
+        let a = Uuid::from_u128(1);
+        let b = Uuid::from_u128(2);
+        world.spawn_player(a, "a".into());
+        world.spawn_bot(b, "b".into(), Difficulty::Normal);
+        world.food.clear();
+        place(&mut world, a, Point { x: 1, y: 1 }, Direction::Right);
+        place(&mut world, b, Point { x: 4, y: 5 }, Direction::Right);
+
+        let mut moves = Vec::new();
+        for _ in 0..2 {
+            moves.extend(world.step(&[]).moves);
+        }
+
+        // The player moves once per tick, the bot every other tick.
+        assert_eq!(moves.iter().filter(|(uuid, _, _)| *uuid == a).count(), 2);
+        assert_eq!(moves.iter().filter(|(uuid, _, _)| *uuid == b).count(), 1);
+    }
+
+    #[test]
+    fn teammates_pass_without_friendly_fire() {
+        let mut world = World::new(
+            WorldSettings {
+                teams: 2,
+                friendly_fire: false,
+                ..settings()
+            },
+            1,
+        );
+        let a = Uuid::from_u128(1);
+        let b = Uuid::from_u128(2);
+        let c = Uuid::from_u128(3);
+        world.spawn(a, "a".into(), None, Some(1));
+        world.spawn(b, "b".into(), None, Some(1));
+        world.spawn_player(c, "c".into());
+        assert_eq!(world.players[&c].team, Some(0));
+        world.food.clear();
+        place(&mut world, a, Point { x: 2, y: 2 }, Direction::Right);
+        place(&mut world, b, Point { x: 3, y: 3 }, Direction::Down);
+        place(&mut world, c, Point { x: 7, y: 7 }, Direction::Up);
+        grow(&mut world, b, &[Point { x: 3, y: 2 }, Point { x: 3, y: 1 }]);
+        world.players.get_mut(&b).unwrap().score = 4;
+        world.players.get_mut(&c).unwrap().score = 1;
+
+        let events = world.step(&[]);
+
+        assert!(events.deaths.is_empty());
+        assert_eq!(world.players[&a].snake.parts[0], Point { x: 3, y: 2 });
+        assert_eq!(world.team_scores(), vec![1, 4]);
+    }
+
+    #[test]
+    fn last_snake_standing_wins_round() {
+        let mut world = World::new(
+            WorldSettings {
+                respawn: false,
+                ..settings()
+            },
+            1,
+        );
+        let a = Uuid::from_u128(1);
+        let b = Uuid::from_u128(2);
+        let c = Uuid::from_u128(3);
+        world.spawn_player(a, "a".into());
+        world.spawn_player(b, "b".into());
+        world.spawn_player(c, "c".into());
+        assert_eq!(world.alive_count(), 0);
+
+        world.start_round();
+        world.food.clear();
+        place(&mut world, a, Point { x: 0, y: 2 }, Direction::Left);
+        place(&mut world, b, Point { x: 5, y: 5 }, Direction::Right);
+        place(&mut world, c, Point { x: 5, y: 0 }, Direction::Right);
+        let events = world.step(&[(c, Direction::Up)]);
+
+        assert!(events.respawned.is_empty());
+        assert_eq!(world.alive_count(), 1);
+        assert_eq!(world.player_infos().len(), 1);
+        let standings: Vec<Uuid> = world.end_round().iter().map(|s| s.0).collect();
+        assert_eq!(standings, vec![b, c, a]);
+        assert_eq!(world.alive_count(), 0);
+    }
+}
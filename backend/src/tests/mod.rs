@@ -1,6 +1,9 @@
 #[cfg(test)]
 mod tests {
-    use crate::server::{messages::ClientMessage, types::Direction};
+    use crate::server::{
+        messages::{ClientMessage, DEFAULT_ROOM},
+        types::Direction,
+    };
 
     #[test]
     fn deserialization() {
@@ -23,4 +26,29 @@ mod tests {
         let msg: ClientMessage = serde_json::from_str(fake_message).unwrap();
         println! {"{:?}", msg};
     }
+
+    #[test]
+    fn handshake_defaults_to_default_room() {
+        let msg: ClientMessage = serde_json::from_str(r#"{ "Spectate" : {} }"#).unwrap();
+        assert!(matches!(msg, ClientMessage::Spectate { room, .. } if room == DEFAULT_ROOM));
+
+        let msg: ClientMessage =
+            serde_json::from_str(r#"{ "Register" : { "name" : "Bob" } }"#).unwrap();
+        assert!(matches!(msg, ClientMessage::Register { room, .. } if room == DEFAULT_ROOM));
+    }
+
+    #[test]
+    fn message_pack_round_trip() {
+        let msg = ClientMessage::Turn {
+            direction: Direction::Left,
+        };
+        let bytes = rmp_serde::to_vec(&msg).unwrap();
+        let decoded: ClientMessage = rmp_serde::from_slice(&bytes).unwrap();
+        assert!(matches!(
+            decoded,
+            ClientMessage::Turn {
+                direction: Direction::Left
+            }
+        ));
+    }
 }